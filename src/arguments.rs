@@ -1,9 +1,41 @@
+use std::path::PathBuf;
+
 use bevy::prelude::Resource;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+use crate::world::chunk::Mesher;
 
 #[derive(Parser, Resource, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Context {
     #[arg(long)]
     debug_asset_loader: bool,
+
+    /// Name of the world save to open
+    #[arg(long, default_value = "world")]
+    pub world: String,
+
+    /// Directory with user content packs, replaces the default one
+    #[arg(long, env = "VOXELBOX_CONTENT_DIR")]
+    pub content_dir: Option<PathBuf>,
+
+    /// Mesher used for chunks, defaults to greedy
+    #[arg(long, value_enum)]
+    pub mesher: Option<Mesher>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check content packs for errors and exit
+    ValidatePacks {
+        /// Content directory to check, defaults to all content directories
+        path: Option<PathBuf>,
+
+        /// Treat warnings as errors
+        #[arg(long)]
+        deny_warnings: bool,
+    },
 }
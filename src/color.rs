@@ -90,6 +90,15 @@ where
     deserializer.deserialize_str(HexColorVisitor)
 }
 
+/// Deserializes a hex color into `Some`; use with `#[serde(default)]` to get
+/// `None` for missing colors.
+pub fn deserialize_optional_hex_color<'de, D>(deserializer: D) -> Result<Option<Vec4>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_hex_color(deserializer).map(Some)
+}
+
 struct DecimalColor(u32);
 
 impl From<DecimalColor> for Color {
@@ -1,101 +1,464 @@
+use crate::arguments::Context;
 use crate::error::ResourceError;
 use crate::math::side::Side;
+use crate::world::save::saves_dir;
+use vfs::{is_archive, PackFile, PackFs};
 use crate::MaterialID;
 use ahash::HashMap;
 use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
 use bevy::render::render_resource::ShaderType;
-use serde::Deserialize;
+use semver::{Version, VersionReq};
+use serde::de::{IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
 use std::collections::btree_map::BTreeMap;
+use std::collections::BTreeSet;
 use std::hash::Hash;
 use std::mem::size_of;
 use std::path::{Path, PathBuf};
+use texture_packer::exporter::ImageExporter;
+use texture_packer::importer::ImageImporter;
+use texture_packer::texture::Texture;
+use texture_packer::{TexturePacker, TexturePackerConfig};
+
+pub mod validate;
+pub mod vfs;
+pub mod watch;
 
 static CONTENT_DIR: &str = "content";
+static PACK_INFO_FILE: &str = "info.ron";
+static MATERIALS_DIR: &str = "materials";
+static OVERRIDES_DIR: &str = "overrides";
+static MATERIAL_PROPERTIES_FILE: &str = "properties.ron";
 
-pub fn content_dir() -> PathBuf {
-    let path = {
-        #[cfg(not(feature = "dev"))]
-        {
-            dirs::data_dir()
-                .map(|data| data.join(NAME).join(CONTENT_DIR))
-                .unwrap_or(PathBuf::new().join(CONTENT_DIR))
-        }
-        #[cfg(feature = "dev")]
-        {
-            PathBuf::new().join(CONTENT_DIR)
+/// Where a content root comes from. Later sources take precedence when
+/// several roots contain a pack with the same ID.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ContentSource {
+    /// Content shipped with the game.
+    #[default]
+    BuiltIn,
+    /// Content installed by the user.
+    User,
+    /// Content stored in the opened world save.
+    World,
+}
+
+/// Directory containing content packs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentRoot {
+    pub path: PathBuf,
+    pub source: ContentSource,
+}
+
+impl ContentRoot {
+    pub fn new(path: impl Into<PathBuf>, source: ContentSource) -> ContentRoot {
+        ContentRoot {
+            path: path.into(),
+            source,
         }
-    };
+    }
+}
+
+/// Content roots searched for packs, in order of increasing precedence.
+#[derive(Debug, Clone, Resource)]
+pub struct ContentRoots(pub Vec<ContentRoot>);
+
+/// Directory with content shipped with the game.
+pub fn builtin_content_dir() -> PathBuf {
+    #[cfg(not(feature = "dev"))]
+    {
+        std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join(CONTENT_DIR)))
+            .unwrap_or(PathBuf::new().join(CONTENT_DIR))
+    }
+    #[cfg(feature = "dev")]
+    {
+        PathBuf::new().join(CONTENT_DIR)
+    }
+}
+
+/// Default directory for content installed by the user.
+///
+/// Development builds only use built-in content unless a user directory is
+/// provided explicitly.
+pub fn user_content_dir() -> Option<PathBuf> {
+    #[cfg(not(feature = "dev"))]
+    {
+        dirs::data_dir().map(|data| data.join(crate::NAME).join(CONTENT_DIR))
+    }
+    #[cfg(feature = "dev")]
+    {
+        None
+    }
+}
+
+/// Returns content roots selected by `context`, in order of increasing
+/// precedence: built-in content, user content and content of the opened
+/// world.
+///
+/// [`Context::content_dir`] replaces the default user content directory.
+pub fn content_roots(context: &Context) -> Vec<ContentRoot> {
+    let mut result = vec![ContentRoot::new(
+        builtin_content_dir(),
+        ContentSource::BuiltIn,
+    )];
 
-    if !path.exists() {
-        std::fs::create_dir_all(&path).expect("unable to create content directory");
+    if let Some(path) = context.content_dir.clone().or_else(user_content_dir) {
+        if !path.exists() {
+            if let Err(err) = std::fs::create_dir_all(&path) {
+                tracing::warn!(
+                    "Unable to create content directory '{}': {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+        result.push(ContentRoot::new(path, ContentSource::User));
     }
 
-    path
+    result.push(ContentRoot::new(
+        saves_dir().join(&context.world).join(CONTENT_DIR),
+        ContentSource::World,
+    ));
+
+    // the same directory can be selected for several sources
+    let mut seen = Vec::new();
+    result.retain(|root| {
+        let path = root.path.canonicalize().unwrap_or(root.path.clone());
+        let duplicate = seen.contains(&path);
+        seen.push(path);
+        !duplicate
+    });
+    result
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ContentPack {
+    /// Directory or archive the pack was read from.
     #[serde(skip)]
     pub path: PathBuf,
+    #[serde(skip)]
+    pub fs: PackFs,
+    /// Kind of content root the pack was found in.
+    #[serde(skip)]
+    pub source: ContentSource,
 
     pub id: String,
     pub name: String,
     pub authors: Vec<String>,
+    #[serde(default = "ContentPack::default_version")]
+    pub version: Version,
+
+    /// IDs of packs required by this one, with accepted versions.
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, VersionReq>,
+    /// IDs of packs that are loaded before this one if present.
+    #[serde(default)]
+    pub load_after: Vec<String>,
 }
 
 impl ContentPack {
-    pub fn init(path: impl AsRef<Path>) -> Result<ContentPack, ResourceError> {
-        let info_path = path.as_ref().join("info.ron");
+    pub fn init(
+        path: impl AsRef<Path>,
+        source: ContentSource,
+    ) -> Result<ContentPack, ResourceError> {
+        let fs = PackFs::open(path.as_ref())?;
+        let info_path = Path::new(PACK_INFO_FILE);
 
-        if !info_path.exists() {
-            return Err(ResourceError::InvalidPath(info_path));
+        if !fs.is_file(info_path) {
+            return Err(ResourceError::InvalidPath(fs.full_path(info_path)));
         }
 
-        let info_str = std::fs::read_to_string(info_path)?;
+        let info_str = fs.read_to_string(info_path)?;
 
         let mut result: ContentPack = ron::from_str(&info_str)?;
         result.path = path.as_ref().to_path_buf();
+        result.fs = fs;
+        result.source = source;
 
         Ok(result)
     }
+
+    fn default_version() -> Version {
+        Version::new(0, 0, 0)
+    }
+
+    /// Returns IDs of packs that must be loaded before this one.
+    fn loaded_after(&self) -> impl Iterator<Item = &String> {
+        self.dependencies.keys().chain(self.load_after.iter())
+    }
+
+    /// Directory containing materials, relative to the pack root.
+    #[inline]
+    pub fn materials_path(&self) -> &'static Path {
+        Path::new(MATERIALS_DIR)
+    }
+
+    /// Directory containing `<pack>/<material>` overrides of materials from
+    /// other packs, relative to the pack root.
+    #[inline]
+    pub fn overrides_path(&self) -> &'static Path {
+        Path::new(OVERRIDES_DIR)
+    }
+
+    /// Returns ID of material stored in `material_path` of this pack.
+    pub fn material_id(&self, material_path: &Path) -> Option<MaterialID> {
+        let name = material_path.file_name()?.to_str()?;
+        Some(MaterialID::new(self.id.clone() + ":" + name))
+    }
 }
 
-pub fn content_packs() -> Vec<ContentPack> {
-    let pack_dirs: Vec<PathBuf> = std::fs::read_dir(content_dir())
-        .expect("unable to access content directory")
-        .filter_map(|it| it.ok().map(|e| e.path()))
-        .collect();
+/// Reads definition of material stored in `material_path` of pack `fs`.
+pub fn read_material_properties(
+    fs: &PackFs,
+    material_path: &Path,
+) -> Result<MaterialDefinition, ResourceError> {
+    let prop_str = fs.read_to_string(&material_path.join(MATERIAL_PROPERTIES_FILE))?;
+    Ok(ron::from_str(&prop_str)?)
+}
 
+/// Reads info files of all content packs in `roots`.
+///
+/// Packs are either directories or zip archives. Returns found packs in no
+/// particular order along with pack paths that couldn't be read. Missing roots
+/// are skipped.
+pub fn find_content_packs(
+    roots: &[ContentRoot],
+) -> (Vec<ContentPack>, Vec<(PathBuf, ResourceError)>) {
     let mut result: Vec<ContentPack> = Vec::new();
-    for dir in pack_dirs {
-        match ContentPack::init(dir) {
-            Ok(it) => {
-                tracing::info!("- Found: {}", it.name);
-                result.push(it);
-            }
-            Err(err) => {
-                tracing::error!("Can't load content pack: {:?}", err);
+    let mut errors = Vec::new();
+    for root in roots {
+        let archives = std::fs::read_dir(&root.path)
+            .map(|dir| {
+                dir.filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| is_archive(p))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let mut paths = subdirectories(&root.path);
+        paths.extend(archives);
+        paths.sort();
+
+        for dir in paths {
+            match ContentPack::init(&dir, root.source) {
+                Ok(it) => result.push(it),
+                Err(err) => errors.push((dir, err)),
             }
         }
     }
+    (result, errors)
+}
+
+pub fn content_packs(roots: &[ContentRoot]) -> Vec<ContentPack> {
+    let (result, errors) = find_content_packs(roots);
+    for (dir, err) in errors {
+        tracing::error!("Can't load content pack '{}': {}", dir.display(), err);
+    }
+    for it in &result {
+        tracing::info!("- Found: {}", it.name);
+    }
+
+    let (result, errors) = order_content_packs(result);
+    for err in errors {
+        tracing::error!("Can't load content pack: {}", err);
+    }
 
     result
 }
 
+/// Orders `packs` so that each pack comes after its dependencies and packs
+/// listed in its `load_after`. Packs that don't depend on each other are
+/// ordered by ID.
+///
+/// A pack replaces packs with the same ID from sources with lower precedence.
+/// Duplicate packs within a source, packs with missing or incompatible
+/// dependencies and packs with cyclic dependencies (along with packs depending
+/// on them) are left out and reported in returned errors; every dependency
+/// cycle is reported separately.
+pub fn order_content_packs(
+    mut packs: Vec<ContentPack>,
+) -> (Vec<ContentPack>, Vec<ResourceError>) {
+    let mut errors = Vec::new();
+
+    packs.sort_by(|a, b| {
+        a.id.cmp(&b.id)
+            .then_with(|| b.source.cmp(&a.source))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    packs.dedup_by(|pack, kept| {
+        if pack.id != kept.id {
+            return false;
+        }
+        if pack.source == kept.source {
+            errors.push(ResourceError::DuplicateContentPack {
+                id: pack.id.clone(),
+                path: pack.path.clone(),
+            });
+        } else {
+            tracing::info!(
+                "Content pack '{}' at {} replaces {}",
+                kept.id,
+                kept.path.display(),
+                pack.path.display()
+            );
+        }
+        true
+    });
+
+    // removing a pack can break packs depending on it, repeat until stable
+    loop {
+        let versions: HashMap<&str, &Version> = packs
+            .iter()
+            .map(|it| (it.id.as_str(), &it.version))
+            .collect();
+
+        let mut broken = BTreeSet::new();
+        for pack in &packs {
+            for (dependency, required) in &pack.dependencies {
+                let error = match versions.get(dependency.as_str()) {
+                    None => ResourceError::MissingDependency {
+                        pack: pack.id.clone(),
+                        dependency: dependency.clone(),
+                        required: required.clone(),
+                    },
+                    Some(found) if !required.matches(found) => {
+                        ResourceError::IncompatibleDependency {
+                            pack: pack.id.clone(),
+                            dependency: dependency.clone(),
+                            required: required.clone(),
+                            found: (*found).clone(),
+                        }
+                    }
+                    Some(_) => continue,
+                };
+                errors.push(error);
+                broken.insert(pack.id.clone());
+            }
+        }
+
+        if broken.is_empty() {
+            break;
+        }
+        packs.retain(|it| !broken.contains(&it.id));
+    }
+
+    let index: HashMap<&str, usize> = packs
+        .iter()
+        .enumerate()
+        .map(|(i, it)| (it.id.as_str(), i))
+        .collect();
+    // before[i] lists packs that have to be loaded before pack i
+    let before: Vec<Vec<usize>> = packs
+        .iter()
+        .map(|pack| {
+            pack.loaded_after()
+                .filter_map(|id| index.get(id.as_str()).copied())
+                .collect()
+        })
+        .collect();
+
+    let mut remaining: Vec<usize> = before.iter().map(Vec::len).collect();
+    let mut ready: BTreeSet<(&str, usize)> = packs
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| remaining[*i] == 0)
+        .map(|(i, it)| (it.id.as_str(), i))
+        .collect();
+    let mut order = Vec::with_capacity(packs.len());
+    while let Some((_, current)) = ready.pop_first() {
+        order.push(current);
+        for (i, deps) in before.iter().enumerate() {
+            let count = deps.iter().filter(|it| **it == current).count();
+            if count > 0 {
+                remaining[i] -= count;
+                if remaining[i] == 0 {
+                    ready.insert((packs[i].id.as_str(), i));
+                }
+            }
+        }
+    }
+
+    if order.len() < packs.len() {
+        // every unordered pack waits on another unordered pack, so each one
+        // is either part of a cycle or (transitively) depends on one
+        let unordered: Vec<usize> = (0..packs.len()).filter(|it| remaining[*it] > 0).collect();
+        // reach[i] lists unordered packs pack i transitively waits on
+        let reach: Vec<BTreeSet<usize>> = (0..packs.len())
+            .map(|i| {
+                let mut seen = BTreeSet::new();
+                let mut stack = vec![i];
+                while let Some(current) = stack.pop() {
+                    for &it in &before[current] {
+                        if remaining[it] > 0 && seen.insert(it) {
+                            stack.push(it);
+                        }
+                    }
+                }
+                seen
+            })
+            .collect();
+
+        // each strongly connected component is reported once; packs are
+        // sorted by ID so components list their members in order
+        let mut cyclic = BTreeSet::new();
+        for &i in &unordered {
+            if cyclic.contains(&i) || !reach[i].contains(&i) {
+                continue;
+            }
+            let component: Vec<usize> = unordered
+                .iter()
+                .copied()
+                .filter(|j| reach[i].contains(j) && reach[*j].contains(&i))
+                .collect();
+            cyclic.extend(component.iter().copied());
+            errors.push(ResourceError::CyclicDependency(
+                component.iter().map(|it| packs[*it].id.clone()).collect(),
+            ));
+        }
+        for &i in &unordered {
+            if cyclic.contains(&i) {
+                continue;
+            }
+            let dependency = reach[i]
+                .iter()
+                .find(|it| cyclic.contains(*it))
+                .expect("unordered pack doesn't depend on a cycle");
+            errors.push(ResourceError::DependsOnCyclicPack {
+                pack: packs[i].id.clone(),
+                dependency: packs[*dependency].id.clone(),
+            });
+        }
+    }
+
+    let mut packs: Vec<Option<ContentPack>> = packs.into_iter().map(Some).collect();
+    let result = order
+        .into_iter()
+        .map(|it| packs[it].take().unwrap())
+        .collect();
+    (result, errors)
+}
+
 #[derive(Resource)]
 pub struct LoadedContentPacks(pub Vec<ContentPack>);
 
-#[derive(Debug, Clone, PartialEq, ShaderType, Deserialize)]
-#[serde(default)]
+/// Properties of a material face, as passed to the chunk shader.
+///
+/// Read from [`FaceDefinition`]s.
+#[derive(Debug, Clone, PartialEq, ShaderType)]
 #[repr(C)]
 pub struct FaceProperties {
-    #[serde(deserialize_with = "crate::color::deserialize_hex_color")]
     pub base_color: Vec4,
 
-    #[serde(skip)]
+    /// Position of the face texture in the atlas, in normalized coordinates.
     pub uv: Vec2,
+    /// Size of the face texture in the atlas, in normalized coordinates.
+    ///
+    /// Zero if the face isn't textured.
+    pub uv_size: Vec2,
 
-    #[serde(deserialize_with = "crate::color::deserialize_hex_color")]
     pub emissive_color: Vec4,
 
     pub roughness: f32,
@@ -127,18 +490,21 @@ impl Default for FaceProperties {
             reflectance: 0.5,
 
             uv: Default::default(),
+            uv_size: Default::default(),
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
-#[serde(untagged)]
+/// Faces of a material, resolved from its definition and the definitions it
+/// inherits from.
+#[derive(Debug, Clone)]
 pub enum BlockFaces {
     Uniform {
         face: FaceProperties,
     },
     Sided {
-        face: FaceProperties,
+        /// Properties of sides without an override.
+        face: Option<FaceProperties>,
         face_override: HashMap<Side, FaceProperties>,
     },
 }
@@ -151,114 +517,829 @@ impl Default for BlockFaces {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl BlockFaces {
+    /// Returns properties of the face on `side`.
+    pub fn get(&self, side: Side) -> Option<&FaceProperties> {
+        match self {
+            BlockFaces::Uniform { face } => Some(face),
+            BlockFaces::Sided {
+                face,
+                face_override,
+            } => face_override.get(&side).or(face.as_ref()),
+        }
+    }
+}
+
+/// Deserializes a value into `Some`; use with `#[serde(default)]` to get
+/// `None` for missing values.
+fn deserialize_some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Face properties as written in `properties.ron`; properties that aren't
+/// specified are taken from the inherited face.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
 #[serde(default)]
-pub struct MaterialProperties {
-    #[serde(deserialize_with = "crate::color::deserialize_hex_color")]
-    pub color: Vec4,
+pub struct FaceDefinition {
+    #[serde(deserialize_with = "crate::color::deserialize_optional_hex_color")]
+    pub base_color: Option<Vec4>,
+    #[serde(deserialize_with = "crate::color::deserialize_optional_hex_color")]
+    pub emissive_color: Option<Vec4>,
+    #[serde(deserialize_with = "deserialize_some")]
+    pub roughness: Option<f32>,
+    #[serde(deserialize_with = "deserialize_some")]
+    pub metallic: Option<f32>,
+    #[serde(deserialize_with = "deserialize_some")]
+    pub reflectance: Option<f32>,
+}
 
-    #[serde(flatten)]
-    pub faces: Option<BlockFaces>,
+impl FaceDefinition {
+    /// Returns `face` with properties specified by this definition replaced.
+    pub fn apply(&self, face: &FaceProperties) -> FaceProperties {
+        FaceProperties {
+            base_color: self.base_color.unwrap_or(face.base_color),
+            emissive_color: self.emissive_color.unwrap_or(face.emissive_color),
+            roughness: self.roughness.unwrap_or(face.roughness),
+            metallic: self.metallic.unwrap_or(face.metallic),
+            reflectance: self.reflectance.unwrap_or(face.reflectance),
+            ..face.clone()
+        }
+    }
 }
 
-impl Default for MaterialProperties {
-    fn default() -> Self {
+/// Contents of a material `properties.ron` file.
+#[derive(Debug, Clone, Default)]
+pub struct MaterialDefinition {
+    /// ID of the material whose properties are inherited; only properties
+    /// specified alongside it are overridden.
+    pub parent: Option<String>,
+    pub color: Option<Vec4>,
+    /// Properties of all sides.
+    pub face: Option<FaceDefinition>,
+    /// Properties of individual sides, applied on top of [`face`](Self::face).
+    pub face_override: HashMap<Side, FaceDefinition>,
+}
+
+impl MaterialDefinition {
+    /// Resolves properties defined by this definition, taking the ones that
+    /// aren't specified from `parent`.
+    ///
+    /// Every side starts from the face `parent` has on it, recolored if
+    /// [`color`](Self::color) is set, and gets [`face`](Self::face) and its
+    /// [override](Self::face_override) applied field by field.
+    pub fn resolve(&self, parent: Option<&MaterialProperties>) -> MaterialProperties {
+        let color = self.color.or(parent.and_then(|it| it.color));
+        let parent_faces = parent.and_then(|it| it.faces.as_ref());
+
+        let default_face = FaceProperties {
+            base_color: color.unwrap_or(MaterialProperties::DEFAULT_COLOR),
+            ..default()
+        };
+        let inherited = |face: Option<&FaceProperties>| {
+            let face = face.unwrap_or(&default_face);
+            let face = FaceProperties {
+                base_color: self.color.unwrap_or(face.base_color),
+                ..face.clone()
+            };
+            match &self.face {
+                Some(definition) => definition.apply(&face),
+                None => face,
+            }
+        };
+
+        let base = inherited(match parent_faces {
+            Some(BlockFaces::Uniform { face }) => Some(face),
+            Some(BlockFaces::Sided { face, .. }) => face.as_ref(),
+            None => None,
+        });
+        let mut face_override = HashMap::default();
+        for side in Side::ALL {
+            let mut face = inherited(parent_faces.and_then(|it| it.get(side)));
+            if let Some(definition) = self.face_override.get(&side) {
+                face = definition.apply(&face);
+            }
+            if face != base {
+                face_override.insert(side, face);
+            }
+        }
+
+        let faces = if face_override.is_empty() {
+            BlockFaces::Uniform { face: base }
+        } else {
+            BlockFaces::Sided {
+                face: Some(base),
+                face_override,
+            }
+        };
         MaterialProperties {
-            color: Vec4::new(1., 1., 1., 1.),
-            faces: None,
+            color,
+            faces: Some(faces),
         }
     }
 }
 
-#[derive(Resource)]
+impl<'de> Deserialize<'de> for MaterialDefinition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(field_identifier, rename_all = "snake_case")]
+        enum Key {
+            Parent,
+            Color,
+            Face,
+            FaceOverride,
+            #[serde(other)]
+            Unknown,
+        }
+
+        struct HexColor(Vec4);
+
+        impl<'de> Deserialize<'de> for HexColor {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                crate::color::deserialize_hex_color(deserializer).map(HexColor)
+            }
+        }
+
+        struct DefinitionVisitor;
+
+        impl<'de> Visitor<'de> for DefinitionVisitor {
+            type Value = MaterialDefinition;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("material properties")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut result = MaterialDefinition::default();
+                while let Some(key) = map.next_key()? {
+                    match key {
+                        Key::Parent => result.parent = Some(map.next_value()?),
+                        Key::Color => result.color = Some(map.next_value::<HexColor>()?.0),
+                        Key::Face => result.face = Some(map.next_value()?),
+                        Key::FaceOverride => result.face_override = map.next_value()?,
+                        // reported by content validation
+                        Key::Unknown => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+                Ok(result)
+            }
+        }
+
+        // material files are written as maps, which RON doesn't accept for
+        // derived structs, and fields can't be flattened as that loses
+        // identifiers of side keys
+        deserializer.deserialize_any(DefinitionVisitor)
+    }
+}
+
+/// Resolved properties of a loaded material.
+#[derive(Debug, Clone, Default)]
+pub struct MaterialProperties {
+    pub color: Option<Vec4>,
+    pub faces: Option<BlockFaces>,
+}
+
+impl MaterialProperties {
+    pub const DEFAULT_COLOR: Vec4 = Vec4::ONE;
+
+    #[inline]
+    pub fn color(&self) -> Vec4 {
+        self.color.unwrap_or(Self::DEFAULT_COLOR)
+    }
+}
+
+#[derive(Default, Resource)]
 pub struct LoadedMaterials {
-    pub properties: BTreeMap<MaterialID, MaterialProperties>,
+    /// Material properties indexed by [`MaterialID::index`].
+    pub properties: Vec<Option<MaterialProperties>>,
     /// MaterialID -> (texture_location, [UVs; 6]);
     pub texture_location: BTreeMap<MaterialID, (u16, [Rect; 6])>,
     //pub face_positions: BTreeMap<MaterialID, [u32; 6]>,
+    /// Atlas containing all material textures.
+    pub atlas: Option<Handle<Image>>,
 }
 
 impl LoadedMaterials {
-    pub fn insert_material(&mut self, id: MaterialID, mut props: MaterialProperties) {
-        if props.faces.is_none() {
-            props.faces = Some(BlockFaces::Uniform {
-                face: FaceProperties {
-                    base_color: props.color,
-                    ..default()
-                },
-            });
-        }
+    /// Returns properties of material `id` if it's loaded.
+    #[inline]
+    pub fn get(&self, id: &MaterialID) -> Option<&MaterialProperties> {
+        self.properties.get(id.index())?.as_ref()
+    }
+
+    #[inline]
+    pub fn contains(&self, id: &MaterialID) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Inserts material `id`, replacing any previously inserted material with
+    /// the same ID.
+    ///
+    /// If `definition` has a parent, properties that aren't specified are
+    /// taken from it, so the parent has to be inserted first. A material can
+    /// name itself as the parent to patch its current properties.
+    pub fn insert_material(
+        &mut self,
+        id: MaterialID,
+        definition: &MaterialDefinition,
+    ) -> Result<(), ResourceError> {
+        let parent = match &definition.parent {
+            Some(parent) => Some(self.get(&MaterialID::new(parent)).ok_or_else(|| {
+                ResourceError::MissingParent {
+                    material: id.to_string(),
+                    parent: parent.clone(),
+                }
+            })?),
+            None => None,
+        };
+        let props = definition.resolve(parent);
 
-        self.properties.insert(id, props);
+        let texture_location = self.texture_location.get(&id).cloned();
+        if self.properties.len() <= id.index() {
+            self.properties.resize(MaterialID::count().max(id.index() + 1), None);
+        }
+        self.properties[id.index()] = Some(props);
         //self.face_positions.insert(id, face_positions);
+
+        // keep textures of reloaded materials
+        if let Some((page, rects)) = texture_location {
+            self.set_texture_location(&id, page, rects);
+        }
+        Ok(())
+    }
+
+    /// Inserts `definitions`, returning errors of ones that couldn't be
+    /// inserted.
+    ///
+    /// Parents can come later within a pack and overrides can target packs
+    /// loaded after them, so definitions are inserted in inheritance depth
+    /// order: a material gets its base definition and all overrides applied
+    /// before materials inheriting from it are resolved. Definitions of the
+    /// same material keep their load order.
+    pub fn insert_all(
+        &mut self,
+        mut definitions: Vec<(MaterialID, MaterialDefinition)>,
+    ) -> Vec<ResourceError> {
+        let roots: Vec<MaterialID> = definitions.iter().map(|(id, _)| *id).collect();
+        let depth = inheritance_depths(&definitions, roots);
+        definitions.sort_by_key(|(id, _)| depth[id]);
+
+        let mut errors = Vec::new();
+        for (id, definition) in &definitions {
+            match self.insert_material(*id, definition) {
+                Ok(()) => tracing::info!("- Material: '{}'", id),
+                Err(err) => errors.push(err),
+            }
+        }
+        errors
+    }
+
+    /// Returns properties of `side` face of material `id`.
+    pub fn face_properties(&self, id: &MaterialID, side: Side) -> Option<&FaceProperties> {
+        self.get(id)?.faces.as_ref()?.get(side)
+    }
+
+    /// Stores atlas `rects` of each material side and updates UVs of its
+    /// faces to match.
+    ///
+    /// Sides with an empty rect keep their current (e.g. inherited) UVs.
+    /// Uniform faces are split into per-side faces if sides use different
+    /// textures.
+    pub fn set_texture_location(&mut self, id: &MaterialID, page: u16, rects: [Rect; 6]) {
+        self.texture_location.insert(*id, (page, rects));
+
+        let Some(Some(props)) = self.properties.get_mut(id.index()) else {
+            return;
+        };
+        let faces = props.faces.get_or_insert_with(BlockFaces::default);
+
+        let sided: [FaceProperties; Side::COUNT] = Side::ALL.map(|side| {
+            let face = faces.get(side).cloned().unwrap_or_default();
+            if rects[side].is_empty() {
+                return face;
+            }
+            FaceProperties {
+                uv: rects[side].min,
+                uv_size: rects[side].size(),
+                ..face
+            }
+        });
+
+        *faces = if sided.iter().all(|it| *it == sided[0]) {
+            BlockFaces::Uniform {
+                face: sided[0].clone(),
+            }
+        } else {
+            BlockFaces::Sided {
+                face: Some(sided[0].clone()),
+                face_override: Side::ALL.into_iter().zip(sided).collect(),
+            }
+        };
     }
 }
 
-pub fn load_content(mut commands: Commands, _asset_server: Res<AssetServer>) {
+/// Returns subdirectories of `path` sorted by name.
+fn subdirectories(path: &Path) -> Vec<PathBuf> {
+    let mut result: Vec<PathBuf> = std::fs::read_dir(path)
+        .map(|dir| {
+            dir.filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_dir())
+                .collect()
+        })
+        .unwrap_or_default();
+    result.sort();
+    result
+}
+
+/// Returns depth of materials inheriting (transitively) from `roots` in the
+/// inheritance tree formed by `definitions`, with `roots` at depth 0.
+///
+/// Overrides name the material they patch as their parent, so they don't add
+/// a level. Depth is bounded so inheritance cycles can't loop forever.
+pub(crate) fn inheritance_depths(
+    definitions: &[(MaterialID, MaterialDefinition)],
+    roots: impl IntoIterator<Item = MaterialID>,
+) -> HashMap<MaterialID, usize> {
+    let parents: Vec<(MaterialID, MaterialID)> = definitions
+        .iter()
+        .filter_map(|(id, props)| Some((*id, MaterialID::new(props.parent.as_ref()?))))
+        .filter(|(id, parent)| id != parent)
+        .collect();
+
+    let mut depth: HashMap<MaterialID, usize> = roots.into_iter().map(|it| (it, 0)).collect();
+    for _ in 0..parents.len() {
+        let mut updated = false;
+        for (id, parent) in &parents {
+            let Some(parent_depth) = depth.get(parent).copied() else {
+                continue;
+            };
+            let current = depth.entry(*id).or_default();
+            if *current < parent_depth + 1 {
+                *current = parent_depth + 1;
+                updated = true;
+            }
+        }
+        if !updated {
+            break;
+        }
+    }
+    depth
+}
+
+/// Material definitions and textures read from content packs.
+#[derive(Debug, Default)]
+pub struct PackContents {
+    /// Material definitions in load order.
+    pub definitions: Vec<(MaterialID, MaterialDefinition)>,
+    pub textures: Vec<(MaterialID, [Option<PackFile>; Side::COUNT])>,
+    /// Material directories that couldn't be read.
+    pub errors: Vec<(PathBuf, ResourceError)>,
+}
+
+impl PackContents {
+    /// Reads materials and material overrides of `packs`, which are expected
+    /// to be in load order.
+    pub fn read(packs: &[ContentPack]) -> PackContents {
+        let mut result = PackContents::default();
+        for pack in packs {
+            result.read_pack(pack);
+        }
+        result
+    }
+
+    fn read_pack(&mut self, pack: &ContentPack) {
+        let material_paths = pack.fs.subdirectories(pack.materials_path());
+        tracing::info!(
+            "Loading {} materials from '{}' ...",
+            material_paths.len(),
+            pack.name
+        );
+        for material_path in material_paths {
+            let id = pack
+                .material_id(&material_path)
+                .expect("can't get material directory name");
+            self.read_material(&pack.fs, id, &material_path, false);
+        }
+
+        for target_path in pack.fs.subdirectories(pack.overrides_path()) {
+            let Some(target) = target_path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            for material_path in pack.fs.subdirectories(&target_path) {
+                let Some(name) = material_path.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                let id = MaterialID::new(format!("{}:{}", target, name));
+                tracing::info!("- Override: '{}' from '{}'", &id, pack.name);
+                self.read_material(&pack.fs, id, &material_path, true);
+            }
+        }
+    }
+
+    fn read_material(
+        &mut self,
+        fs: &PackFs,
+        id: MaterialID,
+        material_path: &Path,
+        is_override: bool,
+    ) {
+        match read_material_properties(fs, material_path) {
+            Ok(mut props) => {
+                if is_override {
+                    // overrides patch the material they replace
+                    props.parent.get_or_insert_with(|| id.to_string());
+                }
+
+                let sides = material_textures(fs, material_path);
+                if sides.iter().any(Option::is_some) {
+                    match self.textures.iter_mut().find(|(it, _)| *it == id) {
+                        // overrides only replace textures they provide
+                        Some((_, current)) => {
+                            for (current, side) in current.iter_mut().zip(sides) {
+                                if side.is_some() {
+                                    *current = side;
+                                }
+                            }
+                        }
+                        None => self.textures.push((id, sides)),
+                    }
+                }
+                self.definitions.push((id, props));
+            }
+            Err(err) => {
+                let path = fs.full_path(&material_path.join(MATERIAL_PROPERTIES_FILE));
+                self.errors.push((path, err));
+            }
+        }
+    }
+}
+
+/// Texture file names looked up in material directories for `side`, most
+/// specific first.
+pub fn face_texture_names(side: Side) -> &'static [&'static str] {
+    match side {
+        Side::East => &["east.png", "side.png", "albedo.png"],
+        Side::West => &["west.png", "side.png", "albedo.png"],
+        Side::Top => &["top.png", "albedo.png"],
+        Side::Bottom => &["bottom.png", "albedo.png"],
+        Side::South => &["south.png", "side.png", "albedo.png"],
+        Side::North => &["north.png", "side.png", "albedo.png"],
+    }
+}
+
+/// Returns texture files used by each side of material stored in
+/// `material_path` of pack `fs`.
+pub fn material_textures(fs: &PackFs, material_path: &Path) -> [Option<PackFile>; Side::COUNT] {
+    Side::ALL.map(|side| {
+        face_texture_names(side)
+            .iter()
+            .map(|name| material_path.join(name))
+            .find(|it| fs.is_file(it))
+            .map(|it| fs.file(it))
+    })
+}
+
+const ATLAS_MAX_SIZE: u32 = 4096;
+
+/// Packs all distinct `textures` into a single atlas image.
+///
+/// Returns the atlas and normalized rects of each material side; sides
+/// without a texture get an empty rect.
+pub fn pack_texture_atlas(
+    textures: &[(MaterialID, [Option<PackFile>; Side::COUNT])],
+) -> Result<(Image, Vec<(MaterialID, [Rect; Side::COUNT])>), ResourceError> {
+    let config = TexturePackerConfig {
+        max_width: ATLAS_MAX_SIZE,
+        max_height: ATLAS_MAX_SIZE,
+        allow_rotation: false,
+        trim: false,
+        texture_outlines: false,
+        border_padding: 0,
+        texture_padding: 2,
+        texture_extrusion: 1,
+        ..default()
+    };
+    let mut packer = TexturePacker::new_skyline(config);
+
+    // files are keyed by their full path as archives can't be told apart
+    // by relative paths
+    let mut files: Vec<(PathBuf, &PackFile)> = textures
+        .iter()
+        .flat_map(|(_, sides)| sides.iter().flatten())
+        .map(|it| (it.full_path(), it))
+        .collect();
+    files.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    files.dedup_by(|(a, _), (b, _)| a == b);
+
+    for (path, file) in files {
+        let image = ImageImporter::import_from_memory(&file.read()?).map_err(|reason| {
+            ResourceError::InvalidTexture {
+                path: path.clone(),
+                reason,
+            }
+        })?;
+        let key = path.to_string_lossy().to_string();
+        packer
+            .pack_own(key, image)
+            .map_err(|err| ResourceError::TexturePacking(format!("{:?}", err)))?;
+    }
+
+    let size = Vec2::new(packer.width() as f32, packer.height() as f32);
+    let rects = textures
+        .iter()
+        .map(|(id, sides)| {
+            let rects = sides.clone().map(|path| {
+                path.and_then(|it| packer.get_frame(&it.full_path().to_string_lossy().to_string()))
+                    .map(|it| {
+                        let min = Vec2::new(it.frame.x as f32, it.frame.y as f32) / size;
+                        let extent = Vec2::new(it.frame.w as f32, it.frame.h as f32) / size;
+                        Rect::from_corners(min, min + extent)
+                    })
+                    .unwrap_or_default()
+            });
+            (*id, rects)
+        })
+        .collect();
+
+    let atlas = ImageExporter::export(&packer, None)
+        .map_err(|err| ResourceError::TexturePacking(err.to_string()))?;
+
+    Ok((
+        Image::from_dynamic(atlas, true, RenderAssetUsages::RENDER_WORLD),
+        rects,
+    ))
+}
+
+pub fn load_content(
+    mut commands: Commands,
+    context: Res<Context>,
+    _asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+) {
     let mut loaded = LoadedMaterials {
-        properties: BTreeMap::new(),
-        texture_location: BTreeMap::new(),
         //buffer: StorageBuffer::default(),
         //face_positions: BTreeMap::new(),
+        ..default()
     };
 
-    let packs = content_packs();
+    let roots = content_roots(&context);
+    let packs = content_packs(&roots);
     match packs.len() {
         0 => tracing::warn!("No content packs found."),
         it => tracing::info!("Loading {} content pack(s)...", it),
     }
 
-    fn process_material(loaded: &mut LoadedMaterials, pack: &ContentPack, material_path: &Path) {
-        let prop_file = material_path.join("properties.ron");
+    let contents = PackContents::read(&packs);
+    for (path, err) in &contents.errors {
+        tracing::error!("Unable to read '{}': {}", path.display(), err);
+    }
+
+    // texture locations are applied when materials get inserted
+    if !contents.textures.is_empty() {
+        match pack_texture_atlas(&contents.textures) {
+            Ok((atlas, rects)) => {
+                tracing::info!(
+                    "Packed textures of {} materials into {}x{} atlas",
+                    rects.len(),
+                    atlas.width(),
+                    atlas.height()
+                );
+                for (id, rects) in rects {
+                    loaded.set_texture_location(&id, 0, rects);
+                }
+                loaded.atlas = Some(images.add(atlas));
+            }
+            Err(err) => {
+                tracing::error!("Unable to build texture atlas: {}", err);
+            }
+        }
+    }
+
+    for err in loaded.insert_all(contents.definitions) {
+        tracing::error!("Unable to load material: {}", err);
+    }
+
+    commands.insert_resource(ContentRoots(roots));
+    commands.insert_resource(LoadedContentPacks(packs));
+    commands.insert_resource(loaded);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let name = material_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .expect("can't get material directory name");
-        let id = pack.id.clone() + ":" + name;
+    fn pack(id: &str, load_after: &[&str]) -> ContentPack {
+        ContentPack {
+            path: PathBuf::from(id),
+            fs: PackFs::default(),
+            source: ContentSource::BuiltIn,
+            id: id.to_string(),
+            name: id.to_string(),
+            authors: Vec::new(),
+            version: ContentPack::default_version(),
+            dependencies: BTreeMap::new(),
+            load_after: load_after.iter().map(|it| it.to_string()).collect(),
+        }
+    }
 
-        // TODO: Texture packing
+    #[test]
+    fn reports_every_dependency_cycle() {
+        let packs = vec![
+            pack("a", &["b"]),
+            pack("b", &["a"]),
+            pack("c", &["c"]),
+            pack("d", &["a"]),
+            pack("e", &["d"]),
+            pack("f", &[]),
+        ];
+        let (ordered, errors) = order_content_packs(packs);
 
-        if let Ok(prop_str) = std::fs::read_to_string(prop_file) {
-            match ron::from_str::<MaterialProperties>(&prop_str) {
-                Ok(props) => {
-                    tracing::info!("- Material: '{}'", &id);
-                    loaded.insert_material(MaterialID::new(id), props);
+        let ordered: Vec<&str> = ordered.iter().map(|it| it.id.as_str()).collect();
+        assert_eq!(ordered, ["f"]);
+
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                ResourceError::CyclicDependency(vec!["a".into(), "b".into()]).to_string(),
+                ResourceError::CyclicDependency(vec!["c".into()]).to_string(),
+                ResourceError::DependsOnCyclicPack {
+                    pack: "d".into(),
+                    dependency: "a".into(),
                 }
-                Err(err) => {
-                    tracing::error!("Unable to read '{}' properties file: {:#?}", &id, err);
+                .to_string(),
+                ResourceError::DependsOnCyclicPack {
+                    pack: "e".into(),
+                    dependency: "a".into(),
                 }
-            }
-        } else {
-            tracing::error!("Unable to read '{}' properties file.", &id);
+                .to_string(),
+            ]
+        );
+    }
+
+    fn depending(id: &str, version: &str, dependencies: &[(&str, &str)]) -> ContentPack {
+        ContentPack {
+            version: Version::parse(version).unwrap(),
+            dependencies: dependencies
+                .iter()
+                .map(|(id, req)| (id.to_string(), VersionReq::parse(req).unwrap()))
+                .collect(),
+            ..pack(id, &[])
         }
     }
 
-    fn process_pack(loaded: &mut LoadedMaterials, pack: &ContentPack) {
-        let materials_path = pack.path.as_path().join("materials");
-        if let Ok(materials_dir) = std::fs::read_dir(materials_path) {
-            let material_metas: Vec<std::fs::DirEntry> = materials_dir
-                .filter_map(|e| e.ok())
-                .filter(|d| d.metadata().unwrap().is_dir())
-                .collect();
+    #[test]
+    fn missing_dependency_removes_dependants() {
+        let packs = vec![
+            depending("a", "1.0.0", &[("x", "^1")]),
+            depending("b", "1.0.0", &[("a", "^1")]),
+            pack("c", &[]),
+        ];
+        let (ordered, errors) = order_content_packs(packs);
 
-            tracing::info!(
-                "Loading {} materials from '{}' ...",
-                material_metas.len(),
-                pack.name
-            );
+        let ordered: Vec<&str> = ordered.iter().map(|it| it.id.as_str()).collect();
+        assert_eq!(ordered, ["c"]);
+
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                ResourceError::MissingDependency {
+                    pack: "a".into(),
+                    dependency: "x".into(),
+                    required: VersionReq::parse("^1").unwrap(),
+                }
+                .to_string(),
+                ResourceError::MissingDependency {
+                    pack: "b".into(),
+                    dependency: "a".into(),
+                    required: VersionReq::parse("^1").unwrap(),
+                }
+                .to_string(),
+            ]
+        );
+    }
 
-            for material_meta in material_metas {
-                let material_path = material_meta.path();
-                process_material(loaded, pack, &material_path);
+    #[test]
+    fn incompatible_dependency_is_left_out() {
+        let packs = vec![
+            depending("a", "1.2.0", &[]),
+            depending("b", "1.0.0", &[("a", "^2")]),
+            depending("c", "1.0.0", &[("a", ">=1.1")]),
+        ];
+        let (ordered, errors) = order_content_packs(packs);
+
+        let ordered: Vec<&str> = ordered.iter().map(|it| it.id.as_str()).collect();
+        assert_eq!(ordered, ["a", "c"]);
+
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [ResourceError::IncompatibleDependency {
+                pack: "b".into(),
+                dependency: "a".into(),
+                required: VersionReq::parse("^2").unwrap(),
+                found: Version::new(1, 2, 0),
             }
-        }
+            .to_string()]
+        );
+    }
+
+    #[test]
+    fn loads_dependencies_first() {
+        let packs = vec![
+            pack("a", &["b"]),
+            depending("b", "1.0.0", &[("c", "^0.3")]),
+            depending("c", "0.3.1", &[]),
+            pack("d", &[]),
+        ];
+        let (ordered, errors) = order_content_packs(packs);
+
+        let ordered: Vec<&str> = ordered.iter().map(|it| it.id.as_str()).collect();
+        // packs without ordering constraints between them are sorted by ID
+        assert_eq!(ordered, ["c", "b", "a", "d"]);
+        assert!(errors.is_empty());
     }
 
-    for pack in &packs {
-        process_pack(&mut loaded, pack);
+    fn definition(source: &str) -> MaterialDefinition {
+        ron::from_str(source).unwrap()
     }
 
-    commands.insert_resource(LoadedContentPacks(packs));
-    commands.insert_resource(loaded);
+    #[test]
+    fn reads_bare_parent_and_side_keys() {
+        let definition = definition(
+            r##"{
+                parent: "test:parent",
+                face: (roughness: 0.25),
+                face_override: { Top: (base_color: "#ff0000") },
+            }"##,
+        );
+        assert_eq!(definition.parent.as_deref(), Some("test:parent"));
+        assert_eq!(definition.face.unwrap().roughness, Some(0.25));
+        assert_eq!(
+            definition.face_override[&Side::Top].base_color,
+            Some(Vec4::new(1., 0., 0., 1.))
+        );
+    }
+
+    #[test]
+    fn children_inherit_overridden_parent_by_field() {
+        let [parent, child] =
+            ["parent", "child"].map(|it| MaterialID::new(format!("test:inherit_{}", it)));
+        let definitions = vec![
+            // sided child listed before its parent
+            (
+                child,
+                definition(
+                    r#"{
+                        parent: "test:inherit_parent",
+                        face: (metallic: 0.75),
+                        face_override: { Top: (roughness: 0.125) },
+                    }"#,
+                ),
+            ),
+            (
+                parent,
+                definition(r##"{ color: "#0000ff", face: (roughness: 0.25, metallic: 0.5) }"##),
+            ),
+            // override of the parent from a later pack
+            (
+                parent,
+                definition(r#"{ parent: "test:inherit_parent", face: (reflectance: 1.0) }"#),
+            ),
+        ];
+        let mut loaded = LoadedMaterials::default();
+        assert!(loaded.insert_all(definitions).is_empty());
+
+        let parent_face = loaded.face_properties(&parent, Side::Top).unwrap();
+        assert_eq!(parent_face.metallic, 0.5);
+        assert_eq!(parent_face.reflectance, 1.0);
+
+        let side = loaded.face_properties(&child, Side::East).unwrap();
+        assert_eq!(side.base_color, Vec4::new(0., 0., 1., 1.));
+        assert_eq!(side.roughness, 0.25);
+        assert_eq!(side.metallic, 0.75);
+        assert_eq!(side.reflectance, 1.0);
+
+        let top = loaded.face_properties(&child, Side::Top).unwrap();
+        assert_eq!(top.base_color, Vec4::new(0., 0., 1., 1.));
+        assert_eq!(top.roughness, 0.125);
+        assert_eq!(top.metallic, 0.75);
+    }
+
+    #[test]
+    fn missing_parent_is_reported() {
+        let id = MaterialID::new("test:inherit_orphan");
+        let definitions = vec![(id, definition(r#"{ parent: "test:inherit_missing" }"#))];
+        let mut loaded = LoadedMaterials::default();
+        let errors = loaded.insert_all(definitions);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], ResourceError::MissingParent { .. }));
+        assert!(!loaded.contains(&id));
+    }
 }
@@ -0,0 +1,501 @@
+//! Checking content packs for errors without starting the game.
+//!
+//! Runs the same loading pipeline as [`load_content`](super::load_content)
+//! and additionally flags unknown keys, duplicate IDs and textures that no
+//! material side uses.
+
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use ahash::{HashMap, HashMapExt};
+use ron::error::Position;
+use serde::de::{IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+
+use crate::error::ResourceError;
+
+use super::{
+    find_content_packs, material_textures, order_content_packs, pack_texture_atlas,
+    ContentPack, ContentRoot, LoadedMaterials, MaterialDefinition, PackContents,
+    MATERIAL_PROPERTIES_FILE, PACK_INFO_FILE,
+};
+use super::vfs::PackFs;
+
+// Keep in sync with fields of ContentPack, MaterialDefinition and
+// FaceDefinition; checked by tests below.
+const PACK_INFO_KEYS: &[&str] = &[
+    "id",
+    "name",
+    "authors",
+    "version",
+    "dependencies",
+    "load_after",
+];
+const MATERIAL_KEYS: &[&str] = &["parent", "color", "face", "face_override"];
+const FACE_KEYS: &[&str] = &[
+    "base_color",
+    "emissive_color",
+    "roughness",
+    "metallic",
+    "reflectance",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    pub path: Option<PathBuf>,
+    pub position: Option<Position>,
+    pub message: String,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+            if let Some(position) = self.position {
+                write!(f, "{}:{}:", position.line, position.col)?;
+            }
+            f.write_str(" ")?;
+        }
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    fn push(&mut self, severity: Severity, path: Option<&Path>, message: impl ToString) {
+        self.issues.push(Issue {
+            severity,
+            path: path.map(Path::to_path_buf),
+            position: None,
+            message: message.to_string(),
+        });
+    }
+
+    fn error(&mut self, path: Option<&Path>, message: impl ToString) {
+        self.push(Severity::Error, path, message);
+    }
+
+    fn warning(&mut self, path: Option<&Path>, message: impl ToString) {
+        self.push(Severity::Warning, path, message);
+    }
+
+    /// Reports `err`, including its position if it's a RON parsing error.
+    fn resource_error(&mut self, path: Option<&Path>, err: ResourceError) {
+        match err {
+            ResourceError::Ron(err) => self.issues.push(Issue {
+                severity: Severity::Error,
+                path: path.map(Path::to_path_buf),
+                position: Some(err.position),
+                message: err.code.to_string(),
+            }),
+            ResourceError::DuplicateContentPack { id, path } => {
+                let path = path.join(PACK_INFO_FILE);
+                self.error(Some(&path), format!("duplicate content pack ID '{}'", id));
+            }
+            other => self.error(path, other),
+        }
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|it| it.severity == severity)
+            .count()
+    }
+
+    pub fn is_ok(&self, deny_warnings: bool) -> bool {
+        self.count(Severity::Error) == 0 && (!deny_warnings || self.count(Severity::Warning) == 0)
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        writeln!(
+            f,
+            "{} error(s), {} warning(s)",
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        )
+    }
+}
+
+/// Bare identifier, as used for keys of `properties.ron` maps.
+struct Identifier(String);
+
+impl<'de> Deserialize<'de> for Identifier {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct IdentifierVisitor;
+
+        impl<'de> Visitor<'de> for IdentifierVisitor {
+            type Value = Identifier;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("an identifier")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(Identifier(v.to_string()))
+            }
+        }
+
+        deserializer.deserialize_identifier(IdentifierVisitor)
+    }
+}
+
+/// Entries of a map or struct with identifier keys.
+///
+/// [`ron::Value`] can't be used for these as it discards identifiers used as
+/// map keys.
+struct IdentifierMap<V>(Vec<(String, V)>);
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for IdentifierMap<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MapVisitor<V>(PhantomData<V>);
+
+        impl<'de, V: Deserialize<'de>> Visitor<'de> for MapVisitor<V> {
+            type Value = IdentifierMap<V>;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("a map")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut result = Vec::new();
+                while let Some(Identifier(key)) = map.next_key()? {
+                    result.push((key, map.next_value()?));
+                }
+                Ok(IdentifierMap(result))
+            }
+        }
+
+        deserializer.deserialize_any(MapVisitor(PhantomData))
+    }
+}
+
+/// Keys of a `properties.ron` file and keys of the faces it defines.
+#[derive(Default)]
+struct MaterialKeys {
+    keys: Vec<String>,
+    faces: Vec<(String, Vec<String>)>,
+}
+
+impl<'de> Deserialize<'de> for MaterialKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeysVisitor;
+
+        impl<'de> Visitor<'de> for KeysVisitor {
+            type Value = MaterialKeys;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("material properties")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut result = MaterialKeys::default();
+                while let Some(Identifier(key)) = map.next_key()? {
+                    match key.as_str() {
+                        "face" => {
+                            let IdentifierMap(face) =
+                                map.next_value::<IdentifierMap<IgnoredAny>>()?;
+                            let keys = face.into_iter().map(|it| it.0).collect();
+                            result.faces.push((key.clone(), keys));
+                        }
+                        "face_override" => {
+                            let IdentifierMap(sides) =
+                                map.next_value::<IdentifierMap<IdentifierMap<IgnoredAny>>>()?;
+                            for (side, IdentifierMap(face)) in sides {
+                                result.faces.push((
+                                    format!("face_override.{}", side),
+                                    face.into_iter().map(|it| it.0).collect(),
+                                ));
+                            }
+                        }
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                    result.keys.push(key);
+                }
+                Ok(result)
+            }
+        }
+
+        // material definitions accept both map and struct syntax
+        deserializer.deserialize_any(KeysVisitor)
+    }
+}
+
+fn check_pack_info(report: &mut ValidationReport, pack: &ContentPack) {
+    let path = pack.fs.full_path(Path::new(PACK_INFO_FILE));
+    let Ok(info) = pack.fs.read_to_string(Path::new(PACK_INFO_FILE)) else {
+        return;
+    };
+    // parsing errors are reported when packs are read
+    let Ok(IdentifierMap(entries)) = ron::from_str::<IdentifierMap<IgnoredAny>>(&info) else {
+        return;
+    };
+
+    for (key, _) in entries {
+        if !PACK_INFO_KEYS.contains(&key.as_str()) {
+            report.error(Some(&path), format!("unknown key '{}'", key));
+        }
+    }
+}
+
+fn check_material(report: &mut ValidationReport, fs: &PackFs, material_path: &Path) {
+    let path = fs.full_path(&material_path.join(MATERIAL_PROPERTIES_FILE));
+    let Ok(properties) = fs.read_to_string(&material_path.join(MATERIAL_PROPERTIES_FILE)) else {
+        return;
+    };
+    let shape = match ron::from_str::<MaterialKeys>(&properties) {
+        Ok(it) => it,
+        Err(err) => {
+            // invalid files are reported when materials are read, anything
+            // else means keys of a valid file couldn't be checked
+            if ron::from_str::<MaterialDefinition>(&properties).is_ok() {
+                report.resource_error(Some(&path), err.into());
+            }
+            return;
+        }
+    };
+
+    for (i, key) in shape.keys.iter().enumerate() {
+        if !MATERIAL_KEYS.contains(&key.as_str()) {
+            report.error(Some(&path), format!("unknown key '{}'", key));
+        }
+        if shape.keys[..i].contains(key) {
+            report.error(Some(&path), format!("duplicate key '{}'", key));
+        }
+    }
+    for (name, keys) in &shape.faces {
+        for key in keys {
+            if !FACE_KEYS.contains(&key.as_str()) {
+                report.error(Some(&path), format!("unknown key '{}.{}'", name, key));
+            }
+        }
+    }
+
+    let used = material_textures(fs, material_path);
+    for texture in fs.files(material_path) {
+        let is_png = texture.extension().is_some_and(|it| it == "png");
+        if is_png && !used.iter().flatten().any(|it| it.path == texture) {
+            report.warning(
+                Some(&fs.full_path(&texture)),
+                "texture isn't used by any side of the material",
+            );
+        }
+    }
+}
+
+/// Checks all content packs in `roots`.
+pub fn validate_packs(roots: &[ContentRoot]) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    let (packs, errors) = find_content_packs(roots);
+    for (dir, err) in errors {
+        report.resource_error(Some(&dir.join(PACK_INFO_FILE)), err);
+    }
+    for pack in &packs {
+        check_pack_info(&mut report, pack);
+    }
+
+    let (packs, errors) = order_content_packs(packs);
+    for err in errors {
+        report.resource_error(None, err);
+    }
+    if packs.is_empty() {
+        report.warning(None, "no content packs found");
+    }
+
+    let contents = PackContents::read(&packs);
+    for (path, err) in contents.errors {
+        report.resource_error(Some(&path), err);
+    }
+
+    for pack in &packs {
+        let overrides = pack
+            .fs
+            .subdirectories(pack.overrides_path())
+            .iter()
+            .flat_map(|it| pack.fs.subdirectories(it))
+            .collect::<Vec<_>>();
+        for material_path in pack
+            .fs
+            .subdirectories(pack.materials_path())
+            .into_iter()
+            .chain(overrides)
+        {
+            check_material(&mut report, &pack.fs, &material_path);
+        }
+    }
+
+    // overrides name the material they replace as their parent
+    let mut defined = HashMap::new();
+    for (id, props) in &contents.definitions {
+        if props.parent.as_deref() != Some(id.as_ref()) {
+            *defined.entry(*id).or_insert(0usize) += 1;
+        }
+    }
+    for (id, count) in defined {
+        if count > 1 {
+            report.error(None, format!("material '{}' is defined {} times", id, count));
+        }
+    }
+
+    if !contents.textures.is_empty() {
+        if let Err(err) = pack_texture_atlas(&contents.textures) {
+            report.resource_error(None, err);
+        }
+    }
+
+    let mut loaded = LoadedMaterials::default();
+    for err in loaded.insert_all(contents.definitions) {
+        report.resource_error(None, err);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::FaceDefinition;
+    use crate::math::side::Side;
+
+    /// Returns sorted keys of the RON map or struct in `source`.
+    fn map_keys(source: &str) -> Vec<String> {
+        let IdentifierMap(entries) = ron::from_str::<IdentifierMap<IgnoredAny>>(source).unwrap();
+        let mut result: Vec<String> = entries.into_iter().map(|(key, _)| key).collect();
+        result.sort();
+        result
+    }
+
+    fn sorted(keys: &[&str]) -> Vec<String> {
+        let mut result: Vec<String> = keys.iter().map(ToString::to_string).collect();
+        result.sort();
+        result
+    }
+
+    const FACE: &str = r##"(
+        base_color: "#ff0000",
+        emissive_color: "#00ff00",
+        roughness: 0.1,
+        metallic: 0.2,
+        reflectance: 0.3,
+    )"##;
+
+    /// Checks every field of `face` was read from [`FACE`].
+    fn assert_face_populated(face: &FaceDefinition) {
+        // destructured without `..` so new fields have to be added here
+        let FaceDefinition {
+            base_color,
+            emissive_color,
+            roughness,
+            metallic,
+            reflectance,
+        } = face;
+        assert!(base_color.is_some());
+        assert!(emissive_color.is_some());
+        assert!(roughness.is_some());
+        assert!(metallic.is_some());
+        assert!(reflectance.is_some());
+    }
+
+    #[test]
+    fn pack_info_keys_match_content_pack() {
+        let source = r#"(
+            id: "test",
+            name: "Test",
+            authors: ["Someone"],
+            version: "1.2.3",
+            dependencies: { "other": "^1.0" },
+            load_after: ["another"],
+        )"#;
+        assert_eq!(map_keys(source), sorted(PACK_INFO_KEYS));
+
+        let pack: ContentPack = ron::from_str(source).unwrap();
+        let ContentPack {
+            path: _,
+            fs: _,
+            source: _,
+            id,
+            name,
+            authors,
+            version,
+            dependencies,
+            load_after,
+        } = pack;
+        assert_eq!(id, "test");
+        assert_eq!(name, "Test");
+        assert_eq!(authors, ["Someone"]);
+        assert_eq!(version.to_string(), "1.2.3");
+        assert!(dependencies.contains_key("other"));
+        assert_eq!(load_after, ["another"]);
+    }
+
+    #[test]
+    fn material_keys_match_material_definition() {
+        let source = format!(
+            r##"{{
+                parent: "test:parent",
+                color: "#0000ff",
+                face: {FACE},
+                face_override: {{ Top: {FACE} }},
+            }}"##
+        );
+        assert_eq!(map_keys(&source), sorted(MATERIAL_KEYS));
+        assert_eq!(map_keys(FACE), sorted(FACE_KEYS));
+
+        let definition: MaterialDefinition = ron::from_str(&source).unwrap();
+        let MaterialDefinition {
+            parent,
+            color,
+            face,
+            face_override,
+        } = definition;
+        assert_eq!(parent.as_deref(), Some("test:parent"));
+        assert!(color.is_some());
+        assert_face_populated(&face.expect("face wasn't read"));
+        assert_face_populated(&face_override[&Side::Top]);
+
+        // keys of a valid file are always checked
+        let shape: MaterialKeys = ron::from_str(&source).unwrap();
+        assert_eq!(shape.keys, MATERIAL_KEYS);
+        assert_eq!(shape.faces.len(), 2);
+        for (_, keys) in &shape.faces {
+            assert_eq!(keys, FACE_KEYS);
+        }
+    }
+
+    #[test]
+    fn material_keys_include_unknown_keys() {
+        let source = r#"{
+            colour: "#ffffff",
+            face_override: { Top: (shine: 1.0) },
+        }"#;
+        let shape: MaterialKeys = ron::from_str(source).unwrap();
+        assert_eq!(shape.keys, ["colour", "face_override"]);
+        assert_eq!(
+            shape.faces,
+            [("face_override.Top".to_string(), vec!["shine".to_string()])]
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Read-only access to files of content packs.
+//!
+//! Packs are either plain directories or zip archives. [`PackFs`] hides the
+//! difference, so loading code only deals with paths relative to the pack
+//! root.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use zip::ZipArchive;
+
+use crate::error::ResourceError;
+
+use super::{subdirectories, PACK_INFO_FILE};
+
+pub static ARCHIVE_EXTENSION: &str = "zip";
+
+/// Returns whether `path` looks like a zip archive content pack.
+pub fn is_archive(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|it| it.eq_ignore_ascii_case(ARCHIVE_EXTENSION))
+}
+
+/// Files of a content pack stored in a zip archive.
+#[derive(Debug)]
+pub struct ArchiveFs<R = File> {
+    path: PathBuf,
+    /// Directory within the archive containing the pack.
+    prefix: PathBuf,
+    /// Archive entry indices of files, by path relative to the pack root.
+    files: BTreeMap<PathBuf, usize>,
+    archive: Mutex<ZipArchive<R>>,
+}
+
+impl ArchiveFs {
+    pub fn open(path: &Path) -> Result<ArchiveFs, ResourceError> {
+        ArchiveFs::new(path, ZipArchive::new(File::open(path)?)?)
+    }
+}
+
+impl<R: Read + Seek> ArchiveFs<R> {
+    /// Indexes pack files of `archive` read from `path`.
+    fn new(path: &Path, mut archive: ZipArchive<R>) -> Result<ArchiveFs<R>, ResourceError> {
+        let mut entries = BTreeMap::new();
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+            // skips entries that would escape the archive
+            if let Some(name) = entry.enclosed_name() {
+                entries.insert(name, i);
+            }
+        }
+
+        // packs are often archived along with their directory
+        let prefix = entries
+            .keys()
+            .filter(|it| it.file_name().is_some_and(|name| name == PACK_INFO_FILE))
+            .filter(|it| it.components().count() <= 2)
+            .min_by_key(|it| it.components().count())
+            .and_then(|it| it.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let files = entries
+            .into_iter()
+            .filter_map(|(name, i)| Some((name.strip_prefix(&prefix).ok()?.to_path_buf(), i)))
+            .collect();
+
+        Ok(ArchiveFs {
+            path: path.to_path_buf(),
+            prefix,
+            files,
+            archive: Mutex::new(archive),
+        })
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, ResourceError> {
+        let Some(index) = self.files.get(&normalize(path)) else {
+            return Err(ResourceError::InvalidPath(self.full_path(path)));
+        };
+
+        let mut archive = self.archive.lock().expect("pack archive poisoned");
+        let mut file = archive.by_index(*index)?;
+        // declared size isn't checked until the file is read
+        let mut result = Vec::new();
+        file.read_to_end(&mut result)?;
+        Ok(result)
+    }
+
+    /// Returns sorted paths directly inside `path` which are directories if
+    /// `directories` is set, or files otherwise.
+    fn entries(&self, path: &Path, directories: bool) -> Vec<PathBuf> {
+        let path = normalize(path);
+        let result: BTreeSet<PathBuf> = self
+            .files
+            .keys()
+            .filter_map(|it| {
+                let mut rest = it.strip_prefix(&path).ok()?.components();
+                let name = rest.next()?;
+                let is_dir = rest.next().is_some();
+                (is_dir == directories).then(|| path.join(name))
+            })
+            .collect();
+        result.into_iter().collect()
+    }
+
+    fn full_path(&self, path: &Path) -> PathBuf {
+        self.path.join(&self.prefix).join(path)
+    }
+}
+
+/// Drops `.` components so paths match archive entry names.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|it| *it != Component::CurDir)
+        .collect()
+}
+
+/// Files of a content pack.
+///
+/// Cloning is cheap; archives are shared between clones.
+#[derive(Debug, Clone)]
+pub enum PackFs {
+    Directory(PathBuf),
+    Archive(Arc<ArchiveFs>),
+}
+
+impl Default for PackFs {
+    fn default() -> Self {
+        PackFs::Directory(PathBuf::new())
+    }
+}
+
+impl PackFs {
+    /// Opens pack stored in `path`, which is either a directory or a zip
+    /// archive.
+    pub fn open(path: &Path) -> Result<PackFs, ResourceError> {
+        if path.is_dir() {
+            Ok(PackFs::Directory(path.to_path_buf()))
+        } else if is_archive(path) {
+            Ok(PackFs::Archive(Arc::new(ArchiveFs::open(path)?)))
+        } else {
+            Err(ResourceError::InvalidContentPack {
+                path: path.to_path_buf(),
+            })
+        }
+    }
+
+    /// Returns path of `path` pack file on disk, for archives the path of
+    /// the archive joined with `path`.
+    pub fn full_path(&self, path: &Path) -> PathBuf {
+        match self {
+            PackFs::Directory(root) => root.join(path),
+            PackFs::Archive(archive) => archive.full_path(path),
+        }
+    }
+
+    pub fn is_file(&self, path: &Path) -> bool {
+        match self {
+            PackFs::Directory(root) => root.join(path).is_file(),
+            PackFs::Archive(archive) => archive.files.contains_key(&normalize(path)),
+        }
+    }
+
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>, ResourceError> {
+        match self {
+            PackFs::Directory(root) => Ok(std::fs::read(root.join(path))?),
+            PackFs::Archive(archive) => archive.read(path),
+        }
+    }
+
+    pub fn read_to_string(&self, path: &Path) -> Result<String, ResourceError> {
+        let bytes = self.read(path)?;
+        Ok(String::from_utf8(bytes)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?)
+    }
+
+    /// Returns subdirectories of `path` sorted by name.
+    pub fn subdirectories(&self, path: &Path) -> Vec<PathBuf> {
+        match self {
+            PackFs::Directory(root) => subdirectories(&root.join(path))
+                .into_iter()
+                .filter_map(|it| Some(it.strip_prefix(root).ok()?.to_path_buf()))
+                .collect(),
+            PackFs::Archive(archive) => archive.entries(path, true),
+        }
+    }
+
+    /// Returns files directly inside `path` sorted by name.
+    pub fn files(&self, path: &Path) -> Vec<PathBuf> {
+        match self {
+            PackFs::Directory(root) => {
+                let mut result: Vec<PathBuf> = std::fs::read_dir(root.join(path))
+                    .map(|dir| {
+                        dir.filter_map(|e| e.ok())
+                            .map(|e| e.path())
+                            .filter(|p| p.is_file())
+                            .filter_map(|p| Some(p.strip_prefix(root).ok()?.to_path_buf()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                result.sort();
+                result
+            }
+            PackFs::Archive(archive) => archive.entries(path, false),
+        }
+    }
+
+    pub fn file(&self, path: impl Into<PathBuf>) -> PackFile {
+        PackFile {
+            fs: self.clone(),
+            path: path.into(),
+        }
+    }
+}
+
+/// Single file of a content pack.
+#[derive(Debug, Clone)]
+pub struct PackFile {
+    pub fs: PackFs,
+    /// Path relative to the pack root.
+    pub path: PathBuf,
+}
+
+impl PackFile {
+    pub fn read(&self) -> Result<Vec<u8>, ResourceError> {
+        self.fs.read(&self.path)
+    }
+
+    /// See [`PackFs::full_path`].
+    pub fn full_path(&self) -> PathBuf {
+        self.fs.full_path(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    /// Returns an in-memory archive containing `files` with their path as
+    /// content.
+    fn archive(files: &[&str]) -> ArchiveFs<Cursor<Vec<u8>>> {
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in files {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        let data = writer.finish().unwrap();
+        ArchiveFs::new(Path::new("test.zip"), ZipArchive::new(data).unwrap()).unwrap()
+    }
+
+    fn paths(items: &[&str]) -> Vec<PathBuf> {
+        items.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn detects_pack_directory() {
+        let fs = archive(&[
+            "pack/info.ron",
+            "pack/materials/stone/properties.ron",
+            "pack/materials/stone/top.png",
+            "pack/materials/dirt/properties.ron",
+            "readme.txt",
+        ]);
+        assert_eq!(fs.prefix, Path::new("pack"));
+        assert_eq!(fs.full_path(Path::new("info.ron")), Path::new("test.zip/pack/info.ron"));
+        // files outside of the pack directory aren't part of the pack
+        assert!(!fs.files.contains_key(Path::new("readme.txt")));
+
+        let content = fs.read(Path::new("./materials/stone/top.png")).unwrap();
+        assert_eq!(content, b"pack/materials/stone/top.png");
+        assert!(matches!(
+            fs.read(Path::new("materials/stone/side.png")),
+            Err(ResourceError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn prefers_pack_at_archive_root() {
+        let fs = archive(&["info.ron", "nested/info.ron", "deeply/nested/info.ron"]);
+        assert_eq!(fs.prefix, Path::new(""));
+        assert!(fs.files.contains_key(Path::new("nested/info.ron")));
+
+        // info files nested deeper than a single directory aren't pack roots
+        let fs = archive(&["deeply/nested/info.ron"]);
+        assert_eq!(fs.prefix, Path::new(""));
+    }
+
+    #[test]
+    fn lists_entries() {
+        let fs = archive(&[
+            "pack/info.ron",
+            "pack/materials/stone/properties.ron",
+            "pack/materials/stone/top.png",
+            "pack/materials/dirt/properties.ron",
+        ]);
+        assert_eq!(
+            fs.entries(Path::new("materials"), true),
+            paths(&["materials/dirt", "materials/stone"])
+        );
+        assert!(fs.entries(Path::new("materials"), false).is_empty());
+        assert_eq!(
+            fs.entries(Path::new("./materials/stone"), false),
+            paths(&["materials/stone/properties.ron", "materials/stone/top.png"])
+        );
+        assert_eq!(fs.entries(Path::new(""), false), paths(&["info.ron"]));
+        assert_eq!(fs.entries(Path::new(""), true), paths(&["materials"]));
+    }
+}
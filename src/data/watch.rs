@@ -0,0 +1,240 @@
+//! Hot reloading of material properties.
+//!
+//! [`ContentWatcher`] watches [`ContentRoots`] for changes and re-reads
+//! `properties.ron` files of changed materials and material overrides. Changed
+//! materials are re-inserted along with overrides from every pack and
+//! materials inheriting from them. Chunk materials using them get their face
+//! properties rebuilt without re-meshing the chunks.
+//!
+//! Changing opacity of a material affects which faces are culled, those
+//! changes only show up once affected chunks are re-meshed. Packs stored in
+//! archives aren't reloaded.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Mutex;
+
+use ahash::{HashSet, HashSetExt};
+use bevy::prelude::*;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::world::chunk::chunk_material::ChunkMaterial;
+use crate::world::material::MaterialID;
+
+use super::vfs::PackFs;
+use super::{
+    inheritance_depths, load_content, ContentPack, ContentRoots, LoadedContentPacks,
+    LoadedMaterials, MaterialDefinition, PackContents, MATERIAL_PROPERTIES_FILE,
+};
+
+#[derive(Resource)]
+pub struct ContentWatcher {
+    // events are only received while the watcher is alive
+    _watcher: RecommendedWatcher,
+    events: Mutex<Receiver<notify::Result<notify::Event>>>,
+}
+
+impl ContentWatcher {
+    /// Starts watching `paths` and all of their subdirectories.
+    pub fn new<'a>(paths: impl IntoIterator<Item = &'a Path>) -> notify::Result<ContentWatcher> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        for path in paths {
+            watcher.watch(path, RecursiveMode::Recursive)?;
+        }
+
+        Ok(ContentWatcher {
+            _watcher: watcher,
+            events: Mutex::new(events),
+        })
+    }
+
+    /// Returns material properties files changed since the last call.
+    pub fn changed_properties(&self) -> HashSet<PathBuf> {
+        let events = self.events.lock().expect("content watcher poisoned");
+
+        let mut result = HashSet::new();
+        for event in events.try_iter() {
+            match event {
+                Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                    result.extend(event.paths.into_iter().filter(|it| {
+                        it.file_name()
+                            .is_some_and(|name| name == MATERIAL_PROPERTIES_FILE)
+                    }));
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    tracing::warn!("Content watcher error: {}", err);
+                }
+            }
+        }
+        result
+    }
+}
+
+pub fn watch_content(mut commands: Commands, roots: Res<ContentRoots>) {
+    // roots that don't exist have no packs to reload
+    let paths = roots
+        .0
+        .iter()
+        .map(|it| it.path.as_path())
+        .filter(|it| it.is_dir());
+    match ContentWatcher::new(paths) {
+        Ok(it) => commands.insert_resource(it),
+        Err(err) => {
+            tracing::error!("Unable to watch content directory: {}", err);
+        }
+    }
+}
+
+/// Returns ID of the material defined or overridden by `material_path`
+/// (relative to the pack root) of `pack`.
+fn changed_material(pack: &ContentPack, material_path: &Path) -> Option<MaterialID> {
+    let parent = material_path.parent()?;
+    if parent == pack.materials_path() {
+        return pack.material_id(material_path);
+    }
+    if parent.parent()? == pack.overrides_path() {
+        let target = parent.file_name()?.to_str()?;
+        let name = material_path.file_name()?.to_str()?;
+        return Some(MaterialID::new(format!("{}:{}", target, name)));
+    }
+    None
+}
+
+/// Returns definitions of `changed` materials and materials inheriting from
+/// them, ordered so that parents are inserted before their children.
+///
+/// Definitions of the same material (the base definition followed by
+/// overrides in pack order) keep their load order.
+fn affected_definitions(
+    definitions: Vec<(MaterialID, MaterialDefinition)>,
+    changed: &HashSet<MaterialID>,
+) -> Vec<(MaterialID, MaterialDefinition)> {
+    let depth = inheritance_depths(&definitions, changed.iter().copied());
+    let mut result: Vec<(MaterialID, MaterialDefinition)> = definitions
+        .into_iter()
+        .filter(|(id, _)| depth.contains_key(id))
+        .collect();
+    result.sort_by_key(|(id, _)| depth[id]);
+    result
+}
+
+/// Re-reads changed material properties and updates chunk materials that use
+/// them.
+///
+/// Definitions are re-read from all packs so that overrides from other packs
+/// are applied on top of a changed base definition and materials inheriting
+/// from changed ones pick up the changes.
+pub fn reload_materials(
+    watcher: Res<ContentWatcher>,
+    packs: Res<LoadedContentPacks>,
+    mut loaded: ResMut<LoadedMaterials>,
+    mut chunk_materials: ResMut<Assets<ChunkMaterial>>,
+) {
+    let changed_paths = watcher.changed_properties();
+    if changed_paths.is_empty() {
+        return;
+    }
+
+    let mut changed = HashSet::with_capacity(changed_paths.len());
+    for path in &changed_paths {
+        // archived packs aren't watched
+        let id = packs.0.iter().find_map(|pack| {
+            let PackFs::Directory(root) = &pack.fs else {
+                return None;
+            };
+            // event paths are absolute
+            let root = root.canonicalize().ok()?;
+            let material_path = path.strip_prefix(root).ok()?.parent()?;
+            changed_material(pack, material_path)
+        });
+        changed.extend(id);
+    }
+    if changed.is_empty() {
+        return;
+    }
+
+    let contents = PackContents::read(&packs.0);
+    for (path, err) in &contents.errors {
+        let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if changed_paths.contains(&path) {
+            tracing::error!("Unable to reload {}: {}", path.display(), err);
+        }
+    }
+
+    let definitions = affected_definitions(contents.definitions, &changed);
+    let reloaded: HashSet<MaterialID> = definitions.iter().map(|(id, _)| *id).collect();
+    for err in loaded.insert_all(definitions) {
+        tracing::error!("Unable to reload material: {}", err);
+    }
+    for id in &reloaded {
+        tracing::info!("Reloaded material '{}'", id);
+    }
+
+    // only touch affected assets so unchanged buffers aren't re-uploaded
+    let affected: Vec<AssetId<ChunkMaterial>> = chunk_materials
+        .iter()
+        .filter(|(_, material)| reloaded.iter().any(|id| material.uses_material(id)))
+        .map(|(asset, _)| asset)
+        .collect();
+    for asset in affected {
+        if let Some(material) = chunk_materials.get_mut(asset) {
+            material.refresh_faces(&loaded);
+        }
+    }
+}
+
+pub struct ContentReloadPlugin;
+
+impl Plugin for ContentReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, watch_content.after(load_content))
+            .add_systems(
+                Update,
+                reload_materials.run_if(resource_exists::<ContentWatcher>),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(id: MaterialID, parent: Option<MaterialID>) -> (MaterialID, MaterialDefinition) {
+        let props = MaterialDefinition {
+            parent: parent.map(|it| it.to_string()),
+            ..default()
+        };
+        (id, props)
+    }
+
+    #[test]
+    fn affected_definitions_order_parents_first() {
+        let [base, child, grandchild, other] = ["base", "child", "grandchild", "other"]
+            .map(|it| MaterialID::new(format!("test:reload_{}", it)));
+        let definitions = vec![
+            definition(grandchild, Some(child)),
+            definition(base, None),
+            definition(child, Some(base)),
+            definition(other, None),
+            // override of base from a later pack
+            definition(base, Some(base)),
+        ];
+        let changed = HashSet::from_iter([base]);
+
+        let result: Vec<(MaterialID, Option<String>)> = affected_definitions(definitions, &changed)
+            .into_iter()
+            .map(|(id, props)| (id, props.parent))
+            .collect();
+        assert_eq!(
+            result,
+            [
+                (base, None),
+                (base, Some(base.to_string())),
+                (child, Some(base.to_string())),
+                (grandchild, Some(child.to_string())),
+            ]
+        );
+    }
+}
@@ -4,6 +4,7 @@ use derive_more::{Deref, DerefMut};
 use fly_cam::FlyCamera;
 
 use crate::entity::Health;
+use crate::math::pos::ChunkPos;
 
 pub mod fly_cam;
 
@@ -17,7 +18,7 @@ impl Default for PlayerName {
 }
 
 #[derive(Debug, Default, Deref, DerefMut, Component)]
-pub struct PlayerChunk(UVec3);
+pub struct PlayerChunk(pub ChunkPos);
 
 #[derive(Default, Bundle)]
 pub struct Player {
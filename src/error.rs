@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use semver::{Version, VersionReq};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -7,16 +8,67 @@ pub enum ResourceError {
     InvalidContentPack { path: PathBuf },
     #[error("Invalid path: {0}")]
     InvalidPath(PathBuf),
+    #[error("Content pack '{id}' at {path} is already loaded")]
+    DuplicateContentPack { id: String, path: PathBuf },
+    #[error("Content pack '{pack}' depends on missing pack '{dependency}' ({required})")]
+    MissingDependency {
+        pack: String,
+        dependency: String,
+        required: VersionReq,
+    },
+    #[error("Content pack '{pack}' requires '{dependency}' {required}, found {found}")]
+    IncompatibleDependency {
+        pack: String,
+        dependency: String,
+        required: VersionReq,
+        found: Version,
+    },
+    #[error("Material '{material}' inherits missing material '{parent}'")]
+    MissingParent { material: String, parent: String },
+    #[error("Cyclic dependencies between content packs: {}", .0.join(", "))]
+    CyclicDependency(Vec<String>),
+    #[error("Content pack '{pack}' depends on '{dependency}' which has cyclic dependencies")]
+    DependsOnCyclicPack { pack: String, dependency: String },
     #[error(transparent)]
     Ron(#[from] ron::error::SpannedError),
+    #[error("Invalid texture '{path}': {reason}")]
+    InvalidTexture { path: PathBuf, reason: String },
+    #[error("Unable to pack texture atlas: {0}")]
+    TexturePacking(String),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
     #[error("Vox error: {0}")]
     Vox(&'static str),
     #[error(transparent)]
     Io(#[from] std::io::Error),
 }
 
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Invalid chunk data header")]
+    InvalidHeader,
+    #[error("Unsupported chunk format version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("Chunk palette too large: {0} entries")]
+    PaletteOverflow(usize),
+    #[error("Invalid palette entry: {0}")]
+    InvalidPaletteEntry(#[from] std::string::FromUtf8Error),
+    #[error("Invalid chunk content: {0}")]
+    InvalidContent(&'static str),
+    #[error("Invalid region file: {0}")]
+    InvalidRegion(PathBuf),
+    #[error(transparent)]
+    Ron(#[from] ron::error::SpannedError),
+    #[error(transparent)]
+    RonWrite(#[from] ron::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error(transparent)]
     Resource(#[from] ResourceError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
 }
@@ -2,12 +2,16 @@
 #![feature(generic_const_exprs)]
 #![recursion_limit = "256"]
 
+use bevy::asset::load_internal_asset;
 use bevy::prelude::*;
 use clap::Parser;
 
+use data::watch::ContentReloadPlugin;
 use entity::player::fly_cam::FlyCameraPlugin;
+use world::edit::VoxelWorldPlugin;
+use world::stream::{ChunkStreaming, ChunkStreamingPlugin};
 
-// use crate::world::chunk::chunk_material::{ChunkMaterial, CHUNK_SHADER_HANDLE};
+use crate::world::chunk::chunk_material::{ChunkMaterial, CHUNK_SHADER_HANDLE};
 use crate::world::material::MaterialID;
 
 pub mod arguments;
@@ -25,9 +29,28 @@ pub static NAME: &str = env!("CARGO_BIN_NAME");
 pub static VERSION: &str = env!("CARGO_PKG_VERSION");
 
 fn main() {
+    let context = arguments::Context::parse();
+    if let Some(arguments::Command::ValidatePacks {
+        path,
+        deny_warnings,
+    }) = &context.command
+    {
+        let roots = match path {
+            Some(path) => vec![data::ContentRoot::new(path, data::ContentSource::User)],
+            None => data::content_roots(&context),
+        };
+        let report = data::validate::validate_packs(&roots);
+        print!("{}", report);
+        std::process::exit(if report.is_ok(*deny_warnings) { 0 } else { 1 });
+    }
+
     let mut app = App::new();
 
-    app.insert_resource(arguments::Context::parse());
+    app.insert_resource(ChunkStreaming {
+        mesher: context.mesher.unwrap_or_default(),
+        ..default()
+    });
+    app.insert_resource(context);
 
     app.add_plugins(DefaultPlugins.set(WindowPlugin {
         primary_window: Some(Window {
@@ -37,23 +60,23 @@ fn main() {
         ..default()
     }));
 
-    /*
-    #[cfg(not(feature = "debug"))]
     load_internal_asset!(
         app,
         CHUNK_SHADER_HANDLE,
         "world/chunk/chunk_shader.wgsl",
         Shader::from_wgsl
     );
-    */
-    // TODO: register shader type
 
     app.add_plugins(FlyCameraPlugin)
-        //.add_plugins(MaterialPlugin::<ChunkMaterial>::default())
+        .add_plugins(ChunkStreamingPlugin)
+        .add_plugins(VoxelWorldPlugin)
+        .add_plugins(ContentReloadPlugin)
+        .add_plugins(MaterialPlugin::<ChunkMaterial>::default())
         //.register_asset_loader(VoxLoader)
         //.init_asset::<Vox>()
         .add_systems(Startup, (
             data::load_content,
+            world::save::open_world.pipe(world::save::report_open_error),
             entity::player::spawn_player
         ).chain())
         //.add_systems(Startup, world::spawn_world)
@@ -61,7 +84,6 @@ fn main() {
         //.add_startup_system(ui::debug::setup)
         //.add_system(world::mesh::rebuild_meshes)
         //.add_startup_system(build_triangle)
-        //.add_systems(Update, world::build_fresh_chunks);
         //.add_system(world::track_player_chunk)
     ;
 
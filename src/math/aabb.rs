@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 
+use crate::math::pos::ChunkPos;
 use crate::math::vec::IsVec;
 
 pub trait Contains<T> {
@@ -61,7 +62,16 @@ impl Contains<ChunkPos> for AABB<ChunkPos> {
 }
 
 impl Intersects<ChunkPos> for AABB<ChunkPos> {
+    #[inline]
     fn test_intersects(&self, value: &ChunkPos) -> bool {
-        todo!()
+        self.test_contains(value)
+    }
+}
+
+impl Intersects<AABB<ChunkPos>> for AABB<ChunkPos> {
+    fn test_intersects(&self, value: &AABB<ChunkPos>) -> bool {
+        let (s, e) = (self.start.value, self.end.value);
+        let (os, oe) = (value.start.value, value.end.value);
+        s.x <= oe.x && os.x <= e.x && s.y <= oe.y && os.y <= e.y && s.z <= oe.z && os.z <= e.z
     }
 }
@@ -1,4 +1,4 @@
-use std::ops::Index;
+use std::ops::{Index, IndexMut};
 
 use super::pos::ChunkPos;
 use super::vec::{IVec2, IVec3, UVec2, UVec3, Vec2, Vec3};
@@ -12,6 +12,8 @@ pub enum WorldAxis {
 }
 
 impl WorldAxis {
+    pub const ALL: [WorldAxis; 3] = [WorldAxis::X, WorldAxis::Y, WorldAxis::Z];
+
     #[inline]
     pub const fn as_vec3(self) -> Vec3 {
         [Vec3::X, Vec3::Y, Vec3::Z][self as usize]
@@ -100,6 +102,15 @@ impl Index<WorldAxis> for Vec3 {
         }
     }
 }
+impl IndexMut<WorldAxis> for Vec3 {
+    fn index_mut(&mut self, index: WorldAxis) -> &mut Self::Output {
+        match index {
+            WorldAxis::X => &mut self.x,
+            WorldAxis::Y => &mut self.y,
+            WorldAxis::Z => &mut self.z,
+        }
+    }
+}
 impl Index<WorldAxis> for UVec3 {
     type Output = u32;
 
@@ -111,6 +122,15 @@ impl Index<WorldAxis> for UVec3 {
         }
     }
 }
+impl IndexMut<WorldAxis> for UVec3 {
+    fn index_mut(&mut self, index: WorldAxis) -> &mut Self::Output {
+        match index {
+            WorldAxis::X => &mut self.x,
+            WorldAxis::Y => &mut self.y,
+            WorldAxis::Z => &mut self.z,
+        }
+    }
+}
 impl Index<WorldAxis> for IVec3 {
     type Output = i32;
 
@@ -122,6 +142,15 @@ impl Index<WorldAxis> for IVec3 {
         }
     }
 }
+impl IndexMut<WorldAxis> for IVec3 {
+    fn index_mut(&mut self, index: WorldAxis) -> &mut Self::Output {
+        match index {
+            WorldAxis::X => &mut self.x,
+            WorldAxis::Y => &mut self.y,
+            WorldAxis::Z => &mut self.z,
+        }
+    }
+}
 
 impl Index<WorldAxis> for Vec2 {
     type Output = f32;
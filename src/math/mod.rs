@@ -1,5 +1,7 @@
 pub mod aabb;
 pub mod axis;
 pub mod mat;
+pub mod pos;
+pub mod ray;
 pub mod side;
 pub mod vec;
@@ -0,0 +1,129 @@
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Sub};
+
+use serde::{Deserialize, Serialize};
+
+use super::side::Side;
+use super::vec::{IVec3, UVec3, Vec3};
+
+/// Position of a chunk in chunk coordinates.
+///
+/// Chunk at `(0, 0, 0)` spans world positions from `(0, 0, 0)` up to (but
+/// excluding) the chunk size. Negative positions are valid and grow away from
+/// the origin, so a world position `-0.5` belongs to chunk `-1`.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ChunkPos {
+    pub value: IVec3,
+}
+
+impl ChunkPos {
+    pub const ZERO: ChunkPos = ChunkPos::new(0, 0, 0);
+
+    #[inline]
+    pub const fn new(x: i32, y: i32, z: i32) -> ChunkPos {
+        ChunkPos {
+            value: IVec3::new(x, y, z),
+        }
+    }
+
+    /// Returns position of the chunk containing world position `pos`.
+    #[inline]
+    pub fn from_world(pos: Vec3, chunk_size: UVec3) -> ChunkPos {
+        ChunkPos {
+            value: (pos / chunk_size.as_vec3()).floor().as_ivec3(),
+        }
+    }
+
+    /// Returns world position of the lower corner of this chunk.
+    #[inline]
+    pub fn world_origin(self, chunk_size: UVec3) -> Vec3 {
+        self.block_origin(chunk_size).as_vec3()
+    }
+
+    /// Splits global block position into position of the chunk containing it
+    /// and the position of the block within that chunk.
+    #[inline]
+    pub fn from_block(block: IVec3, chunk_size: UVec3) -> (ChunkPos, UVec3) {
+        let size = chunk_size.as_ivec3();
+        (
+            ChunkPos {
+                value: block.div_euclid(size),
+            },
+            block.rem_euclid(size).as_uvec3(),
+        )
+    }
+
+    /// Returns global block position of the lower corner of this chunk.
+    #[inline]
+    pub fn block_origin(self, chunk_size: UVec3) -> IVec3 {
+        self.value * chunk_size.as_ivec3()
+    }
+
+    /// Converts block position within this chunk into a global block position.
+    #[inline]
+    pub fn to_block(self, local: UVec3, chunk_size: UVec3) -> IVec3 {
+        self.block_origin(chunk_size) + local.as_ivec3()
+    }
+
+    #[inline]
+    pub fn neighbour(self, side: Side) -> ChunkPos {
+        self + side.direction_ivec3()
+    }
+
+    pub fn neighbours(self) -> [ChunkPos; Side::COUNT] {
+        Side::ALL.map(|side| self.neighbour(side))
+    }
+
+    /// Returns offsets of all 26 chunks sharing a face, an edge or a corner
+    /// with a chunk.
+    pub fn neighbour_offsets() -> impl Iterator<Item = IVec3> {
+        (-1..=1)
+            .flat_map(|z| (-1..=1).flat_map(move |y| (-1..=1).map(move |x| IVec3::new(x, y, z))))
+            .filter(|it| *it != IVec3::ZERO)
+    }
+
+    #[inline]
+    pub fn distance_squared(self, other: ChunkPos) -> i32 {
+        (self - other).length_squared()
+    }
+}
+
+impl From<IVec3> for ChunkPos {
+    #[inline]
+    fn from(value: IVec3) -> Self {
+        ChunkPos { value }
+    }
+}
+
+impl From<ChunkPos> for IVec3 {
+    #[inline]
+    fn from(value: ChunkPos) -> Self {
+        value.value
+    }
+}
+
+impl Add<IVec3> for ChunkPos {
+    type Output = ChunkPos;
+
+    #[inline]
+    fn add(self, rhs: IVec3) -> Self::Output {
+        ChunkPos {
+            value: self.value + rhs,
+        }
+    }
+}
+
+impl Sub for ChunkPos {
+    type Output = IVec3;
+
+    #[inline]
+    fn sub(self, rhs: ChunkPos) -> Self::Output {
+        self.value - rhs.value
+    }
+}
+
+impl Display for ChunkPos {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}, {}, {}]", self.value.x, self.value.y, self.value.z)
+    }
+}
@@ -0,0 +1,269 @@
+//! Rays and voxel grid traversal.
+//!
+//! [`VoxelTraversal`] visits every voxel a ray passes through in order, using
+//! the Amanatides & Woo "A Fast Voxel Traversal Algorithm for Ray Tracing"
+//! algorithm. Voxels are unit cubes with integer lower corners, so it works
+//! for both chunk-local and global block coordinates.
+
+use super::axis::WorldAxis;
+use super::side::Side;
+use super::vec::{IVec3, Vec3};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Normalized direction of the ray.
+    pub direction: Vec3,
+}
+
+impl Ray {
+    /// Creates a ray starting at `origin` and pointing in `direction`, which
+    /// doesn't have to be normalized.
+    pub fn new(origin: Vec3, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction: direction.normalize_or_zero(),
+        }
+    }
+
+    /// Returns point at `distance` along the ray.
+    #[inline]
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// Returns voxels the ray passes through up to `max_distance`.
+    ///
+    /// `max_distance` may be infinite, in which case the iterator doesn't end
+    /// unless the ray has zero direction.
+    pub fn voxels(&self, max_distance: f32) -> VoxelTraversal {
+        VoxelTraversal::new(self, max_distance)
+    }
+}
+
+/// Voxel a ray passed through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub pos: IVec3,
+    /// Side of the voxel the ray entered it through.
+    ///
+    /// For the voxel containing the ray origin, it's the side facing against
+    /// the dominant axis of the ray direction.
+    pub side: Side,
+    /// Distance along the ray at which it entered the voxel.
+    pub distance: f32,
+}
+
+/// Iterator over voxels a [`Ray`] passes through, nearest first.
+#[derive(Debug, Clone)]
+pub struct VoxelTraversal {
+    next: RayHit,
+    /// Direction of voxel steps along each axis.
+    step: IVec3,
+    /// Distance along the ray at which it crosses the next voxel boundary of
+    /// each axis.
+    t_max: Vec3,
+    /// Distance along the ray between voxel boundaries of each axis.
+    t_delta: Vec3,
+    max_distance: f32,
+}
+
+impl VoxelTraversal {
+    pub fn new(ray: &Ray, max_distance: f32) -> VoxelTraversal {
+        let pos = ray.origin.floor().as_ivec3();
+        let mut step = IVec3::ZERO;
+        let mut t_max = Vec3::INFINITY;
+        let mut t_delta = Vec3::INFINITY;
+        for axis in WorldAxis::ALL {
+            let d = ray.direction[axis];
+            let o = ray.origin[axis];
+            if d > 0. {
+                step[axis] = 1;
+                t_delta[axis] = 1. / d;
+                t_max[axis] = (pos[axis] as f32 + 1. - o) / d;
+            } else if d < 0. {
+                step[axis] = -1;
+                t_delta[axis] = -1. / d;
+                t_max[axis] = (o - pos[axis] as f32) / -d;
+            }
+        }
+
+        let dominant = WorldAxis::ALL
+            .into_iter()
+            .max_by(|a, b| ray.direction[*a].abs().total_cmp(&ray.direction[*b].abs()))
+            .unwrap_or(WorldAxis::X);
+
+        VoxelTraversal {
+            next: RayHit {
+                pos,
+                side: Side::from_axis(dominant, ray.direction[dominant] > 0.),
+                distance: 0.,
+            },
+            step,
+            t_max,
+            t_delta,
+            max_distance,
+        }
+    }
+}
+
+impl Iterator for VoxelTraversal {
+    type Item = RayHit;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // rays with zero direction never leave the origin voxel; the next
+        // boundary is infinitely far away even if max_distance is infinite
+        if self.next.distance.is_infinite()
+            || self.next.distance > self.max_distance
+            || self.max_distance.is_nan()
+        {
+            return None;
+        }
+        let result = self.next;
+
+        let t = self.t_max;
+        let axis = if t.x < t.y {
+            if t.x < t.z {
+                WorldAxis::X
+            } else {
+                WorldAxis::Z
+            }
+        } else if t.y < t.z {
+            WorldAxis::Y
+        } else {
+            WorldAxis::Z
+        };
+        self.next.pos[axis] += self.step[axis];
+        // moving in positive direction enters the voxel through its negative side
+        self.next.side = Side::from_axis(axis, self.step[axis] > 0);
+        self.next.distance = self.t_max[axis];
+        self.t_max[axis] += self.t_delta[axis];
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::vec::UVec3;
+    use crate::world::chunk::{ChunkStore, SizedGrid, SizedGridMut};
+
+    fn hits(ray: Ray, max_distance: f32) -> Vec<(IVec3, Side)> {
+        ray.voxels(max_distance).map(|hit| (hit.pos, hit.side)).collect()
+    }
+
+    fn distances(ray: Ray, max_distance: f32) -> Vec<f32> {
+        ray.voxels(max_distance).map(|hit| hit.distance).collect()
+    }
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len(), "{:?} != {:?}", a, b);
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn axis_aligned_rays() {
+        let origin = Vec3::splat(0.5);
+        for side in Side::ALL {
+            let ray = Ray::new(origin, side.direction());
+            // moving towards a side enters voxels through the opposite one
+            let expected: Vec<(IVec3, Side)> = (0..4)
+                .map(|i| (side.direction_ivec3() * i, side.opposite()))
+                .collect();
+            assert_eq!(hits(ray, 3.), expected, "moving towards {:?}", side);
+            assert_close(&distances(ray, 3.), &[0., 0.5, 1.5, 2.5]);
+        }
+
+        let ray = Ray::new(origin, Vec3::X);
+        assert_eq!(hits(ray, 1.)[1], (IVec3::X, Side::West));
+        let ray = Ray::new(origin, Vec3::NEG_Y);
+        assert_eq!(hits(ray, 1.)[1], (IVec3::NEG_Y, Side::Top));
+    }
+
+    #[test]
+    fn diagonal_ray() {
+        let ray = Ray::new(Vec3::new(0.25, 0.25, 0.5), Vec3::new(2., 1., 0.));
+        assert_eq!(
+            hits(ray, 3.5),
+            [
+                (IVec3::new(0, 0, 0), Side::West),
+                (IVec3::new(1, 0, 0), Side::West),
+                (IVec3::new(1, 1, 0), Side::Bottom),
+                (IVec3::new(2, 1, 0), Side::West),
+                (IVec3::new(3, 1, 0), Side::West),
+            ]
+        );
+        let s = 5f32.sqrt();
+        assert_close(
+            &distances(ray, 3.5),
+            &[0., 0.375 * s, 0.75 * s, 0.875 * s, 1.375 * s],
+        );
+    }
+
+    #[test]
+    fn origin_on_voxel_boundary() {
+        let origin = Vec3::new(1., 0.5, 0.5);
+        // the origin belongs to the voxel above the boundary
+        let ray = Ray::new(origin, Vec3::X);
+        assert_eq!(hits(ray, 1.), [(IVec3::X, Side::West), (IVec3::new(2, 0, 0), Side::West)]);
+        assert_close(&distances(ray, 1.), &[0., 1.]);
+
+        // moving down it's left immediately
+        let ray = Ray::new(origin, Vec3::NEG_X);
+        assert_eq!(hits(ray, 0.5), [(IVec3::X, Side::East), (IVec3::ZERO, Side::East)]);
+        assert_close(&distances(ray, 0.5), &[0., 0.]);
+    }
+
+    #[test]
+    fn negative_coordinates() {
+        let ray = Ray::new(Vec3::new(-0.5, -1.5, -2.25), Vec3::NEG_Z);
+        assert_eq!(
+            hits(ray, 2.),
+            [
+                (IVec3::new(-1, -2, -3), Side::South),
+                (IVec3::new(-1, -2, -4), Side::South),
+                (IVec3::new(-1, -2, -5), Side::South),
+            ]
+        );
+        assert_close(&distances(ray, 2.), &[0., 0.75, 1.75]);
+    }
+
+    #[test]
+    fn zero_direction() {
+        let ray = Ray::new(Vec3::new(-3.5, 2., 0.5), Vec3::ZERO);
+        assert_eq!(ray.direction, Vec3::ZERO);
+        for max_distance in [0., 10., f32::INFINITY] {
+            let hits: Vec<IVec3> = ray.voxels(max_distance).map(|hit| hit.pos).collect();
+            assert_eq!(hits, [IVec3::new(-4, 2, 0)]);
+        }
+    }
+
+    #[test]
+    fn infinite_distance() {
+        let ray = Ray::new(Vec3::splat(0.5), Vec3::ONE);
+        assert_eq!(ray.voxels(f32::INFINITY).take(100).count(), 100);
+        assert_eq!(ray.voxels(f32::NAN).count(), 0);
+    }
+
+    #[test]
+    fn grid_raycast() {
+        let mut store = ChunkStore::new(UVec3::splat(4));
+        store.set_pos_value(UVec3::new(2, 1, 1), Some(1u32));
+
+        let ray = Ray::new(Vec3::new(-1.5, 1.5, 1.5), Vec3::X);
+        let hit = store.raycast(&ray, 10.).unwrap();
+        assert_eq!(hit.pos, IVec3::new(2, 1, 1));
+        assert_eq!(hit.side, Side::West);
+        assert!((hit.distance - 3.5).abs() < 1e-5);
+
+        // too short, pointing away and passing next to the voxel
+        assert_eq!(store.raycast(&ray, 3.), None);
+        let away = Ray::new(ray.origin, Vec3::NEG_X);
+        assert_eq!(store.raycast(&away, 10.), None);
+        let past = Ray::new(Vec3::new(-1.5, 2.5, 1.5), Vec3::X);
+        assert_eq!(store.raycast(&past, 10.), None);
+    }
+}
@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 
 use super::axis::WorldAxis;
 use super::mat::Mat3;
-use super::vec::{IsVec as _, OuterProductExt as _, UVec2, UVec3, Vec3};
+use super::vec::{IVec3, IsVec as _, OuterProductExt as _, UVec2, UVec3, Vec3};
 
 /// Represents sides of a voxel/AABB/cube.
 ///
@@ -122,11 +122,26 @@ impl Side {
         }
     }
 
+    /// Returns side pointing along `axis` in positive or `negative` direction.
+    #[inline]
+    pub fn from_axis(axis: WorldAxis, negative: bool) -> Side {
+        unsafe {
+            // SAFETY: Inverse of Side::axis; axis is at most 2 so the result
+            // is at most 5 which is a valid Side.
+            std::mem::transmute::<_, Side>(((axis as u8) << 1) | negative as u8)
+        }
+    }
+
     #[inline]
     pub fn direction(self) -> Vec3 {
         self.axis().as_vec3() * ((self as u8 & 0x1) as f32 * -2. + 1.)
     }
 
+    #[inline]
+    pub fn direction_ivec3(self) -> IVec3 {
+        self.axis().as_ivec3() * ((self as u8 & 0x1) as i32 * -2 + 1)
+    }
+
     pub fn rotation_to(self, other: Side) -> Mat3 {
         if self == other {
             return Mat3::IDENTITY;
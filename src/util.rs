@@ -1,42 +1,126 @@
+/// Two-way mapping between strings and dense indices, backing ID types
+/// declared with [`decl_id_type`].
+///
+/// Interned strings are leaked; IDs are only created for content names, so
+/// their number stays small.
+#[derive(Debug, Default)]
+pub struct Interner {
+    names: Vec<&'static str>,
+    indices: ahash::HashMap<&'static str, u32>,
+}
+
+impl Interner {
+    /// Returns index of `name`, assigning the next free one if `name` wasn't
+    /// interned yet.
+    pub fn intern(&mut self, name: &str) -> u32 {
+        if let Some(index) = self.indices.get(name) {
+            return *index;
+        }
+        let index = u32::try_from(self.names.len()).expect("too many interned IDs");
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        self.names.push(name);
+        self.indices.insert(name, index);
+        index
+    }
+
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.indices.get(name).copied()
+    }
+
+    pub fn name(&self, index: u32) -> &'static str {
+        self.names[index as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// Declares an interned string ID type.
+///
+/// IDs are dense indices assigned in order of first use, so comparing and
+/// hashing them is cheap, but they're only meaningful within a single run;
+/// names should be stored instead. IDs listed in braces are interned first
+/// and exposed as constants:
+///
+/// ```ignore
+/// decl_id_type!(MaterialID { AIR: 0 = "air" });
+/// ```
 #[macro_export]
 macro_rules! decl_id_type {
-    ($name: ident) => {
-        // TODO: Interning
-
-        #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-        #[serde(untagged)]
-        pub enum $name {
-            Dynamic(String),
-            Static(&'static str),
-        }
+    ($name: ident $({ $($constant: ident : $index: literal = $value: literal),* $(,)? })?) => {
+        #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct $name(u32);
 
         impl $name {
+            $($(pub const $constant: $name = $name($index);)*)?
+
+            fn interner() -> &'static std::sync::RwLock<$crate::util::Interner> {
+                static INTERNER: std::sync::OnceLock<std::sync::RwLock<$crate::util::Interner>> =
+                    std::sync::OnceLock::new();
+                INTERNER.get_or_init(|| {
+                    #[allow(unused_mut)]
+                    let mut interner = $crate::util::Interner::default();
+                    $($(
+                        let index = interner.intern($value);
+                        assert_eq!(index, $index, "predefined IDs must be listed in order");
+                    )*)?
+                    std::sync::RwLock::new(interner)
+                })
+            }
+
+            /// Returns ID of `id` name, interning it if needed.
             #[must_use]
             pub fn new(id: impl AsRef<str>) -> $name {
-                $name::Dynamic(id.as_ref().to_string())
+                let id = id.as_ref();
+                if let Some(existing) = $name::get(id) {
+                    return existing;
+                }
+                let mut interner = $name::interner().write().expect("ID interner poisoned");
+                $name(interner.intern(id))
             }
 
             #[must_use]
-            pub const fn new_static(id: &'static str) -> $name {
-                $name::Static(id)
+            pub fn new_static(id: &'static str) -> $name {
+                $name::new(id)
+            }
+
+            /// Returns ID of `id` name if it was already interned.
+            pub fn get(id: &str) -> Option<$name> {
+                let interner = $name::interner().read().expect("ID interner poisoned");
+                interner.get(id).map($name)
+            }
+
+            /// Returns dense index of this ID.
+            #[inline]
+            pub const fn index(self) -> usize {
+                self.0 as usize
+            }
+
+            /// Returns number of interned IDs; all indices are lower than it.
+            pub fn count() -> usize {
+                $name::interner().read().expect("ID interner poisoned").len()
+            }
+
+            pub fn name(self) -> &'static str {
+                $name::interner()
+                    .read()
+                    .expect("ID interner poisoned")
+                    .name(self.0)
             }
         }
 
         impl AsRef<str> for $name {
             fn as_ref(&self) -> &str {
-                match self {
-                    $name::Dynamic(it) => it.as_ref(),
-                    $name::Static(it) => it,
-                }
+                self.name()
             }
         }
 
-        impl PartialEq for $name {
-            fn eq(&self, other: &Self) -> bool {
-                self.as_ref() == other.as_ref()
-            }
-        }
-        impl Eq for $name {}
+        // ordered by name so that ordering doesn't depend on interning order
         impl PartialOrd for $name {
             fn partial_cmp(&self, other: &Self) -> std::option::Option<std::cmp::Ordering> {
                 Some(self.cmp(other))
@@ -44,19 +128,22 @@ macro_rules! decl_id_type {
         }
         impl Ord for $name {
             fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-                self.as_ref().cmp(other.as_ref())
+                if self.0 == other.0 {
+                    return std::cmp::Ordering::Equal;
+                }
+                self.name().cmp(other.name())
             }
         }
 
-        impl std::fmt::Display for $name {
+        impl std::fmt::Debug for $name {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-                f.write_str(self.as_ref())
+                write!(f, "{}({:?})", stringify!($name), self.name())
             }
         }
 
-        impl std::hash::Hash for $name {
-            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-                self.as_ref().hash(state)
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+                f.write_str(self.name())
             }
         }
 
@@ -64,7 +151,20 @@ macro_rules! decl_id_type {
             type Target = str;
 
             fn deref(&self) -> &Self::Target {
-                self.as_ref()
+                self.name()
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.name())
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let name = <String as serde::Deserialize>::deserialize(deserializer)?;
+                Ok($name::new(name))
             }
         }
     };
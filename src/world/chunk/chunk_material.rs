@@ -6,9 +6,13 @@ use bevy::render::render_resource::{
 };
 use wgpu::VertexFormat;
 
-use crate::{data::FaceProperties, util};
+use crate::data::{FaceProperties, LoadedMaterials};
+use crate::math::side::Side;
+use crate::world::chunk::mesh::MISSING_VOXEL_FACE;
+use crate::world::material::MaterialID;
+use crate::util;
 
-/// The shader handle for `"parallax_map.wgsl"`.
+/// The shader handle for `"chunk_shader.wgsl"`.
 #[allow(clippy::unreadable_literal)]
 pub const CHUNK_SHADER_HANDLE: Handle<Shader> = util::weak_str_handle("chunk_shader");
 
@@ -19,6 +23,15 @@ pub struct ChunkMaterial {
 
     #[storage(0, read_only)]
     pub face_properties: Vec<FaceProperties>,
+    /// Material face each entry of `face_properties` was built from, `None`
+    /// for voxels with missing material properties.
+    pub face_sources: Vec<Option<(MaterialID, Side)>>,
+
+    /// Texture atlas sampled by faces with non-zero
+    /// [`uv_size`](FaceProperties::uv_size).
+    #[texture(1)]
+    #[sampler(2)]
+    pub atlas: Option<Handle<Image>>,
 }
 
 impl Default for ChunkMaterial {
@@ -27,33 +40,69 @@ impl Default for ChunkMaterial {
             alpha_mode: AlphaMode::Opaque,
             depth_bias: 0.0,
             face_properties: Vec::new(),
+            face_sources: Vec::new(),
+            atlas: None,
         }
     }
 }
 
 impl ChunkMaterial {
+    /// Creates material for a chunk mesh from face sources returned by
+    /// [`MeshBuilder::build`](super::mesh::MeshBuilder::build).
+    pub fn new(face_sources: Vec<Option<(MaterialID, Side)>>, materials: &LoadedMaterials) -> Self {
+        let mut result = ChunkMaterial {
+            face_sources,
+            atlas: materials.atlas.clone(),
+            ..default()
+        };
+        result.refresh_faces(materials);
+        result
+    }
+
+    /// Returns whether any face of this material uses properties of `id`.
+    pub fn uses_material(&self, id: &MaterialID) -> bool {
+        self.face_sources
+            .iter()
+            .flatten()
+            .any(|(source, _)| source == id)
+    }
+
+    /// Rebuilds `face_properties` from current `materials`.
+    ///
+    /// Face indices stored in the mesh stay valid so the mesh doesn't have to
+    /// be rebuilt.
+    pub fn refresh_faces(&mut self, materials: &LoadedMaterials) {
+        self.face_properties = self
+            .face_sources
+            .iter()
+            .map(|source| {
+                source
+                    .as_ref()
+                    .and_then(|(id, side)| materials.face_properties(id, *side))
+                    .unwrap_or(&MISSING_VOXEL_FACE)
+                    .clone()
+            })
+            .collect();
+        self.atlas.clone_from(&materials.atlas);
+    }
+
     pub const ATTRIBUTE_FACE_INDEX: MeshVertexAttribute =
         MeshVertexAttribute::new("Voxel_Index", 2349710119055201991, VertexFormat::Uint32);
+    /// Ambient occlusion of a vertex, `1.0` meaning unoccluded.
+    pub const ATTRIBUTE_OCCLUSION: MeshVertexAttribute =
+        MeshVertexAttribute::new("Voxel_Occlusion", 7190316522805614411, VertexFormat::Float32);
 }
 
+// shader lives next to the source instead of in assets, so it's always
+// embedded into the binary; see main
 impl Material for ChunkMaterial {
-    #[cfg(not(debug_assertions))]
     fn vertex_shader() -> ShaderRef {
         CHUNK_SHADER_HANDLE.into()
     }
-    #[cfg(debug_assertions)]
-    fn vertex_shader() -> ShaderRef {
-        "world/chunk/chunk_shader.wgsl".into()
-    }
 
-    #[cfg(not(debug_assertions))]
     fn fragment_shader() -> ShaderRef {
         CHUNK_SHADER_HANDLE.into()
     }
-    #[cfg(debug_assertions)]
-    fn fragment_shader() -> ShaderRef {
-        "world/chunk/chunk_shader.wgsl".into()
-    }
 
     fn alpha_mode(&self) -> AlphaMode {
         self.alpha_mode
@@ -74,6 +123,7 @@ impl Material for ChunkMaterial {
             Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
             Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
             Self::ATTRIBUTE_FACE_INDEX.at_shader_location(3),
+            Self::ATTRIBUTE_OCCLUSION.at_shader_location(4),
         ])?];
         Ok(())
     }
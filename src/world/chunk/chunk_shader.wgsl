@@ -19,6 +19,7 @@ struct Vertex {
     @location(1) normal: vec3<f32>,
     @location(2) uv: vec2<f32>,
     @location(3) face_index: u32,
+    @location(4) occlusion: f32,
 };
 
 struct VertexOutput {
@@ -27,6 +28,7 @@ struct VertexOutput {
     @location(1) normal: vec3<f32>,
     @location(2) uv: vec2<f32>,
     @location(3) face_index: u32,
+    @location(4) occlusion: f32,
 };
 
 @vertex
@@ -37,6 +39,7 @@ fn vertex(vertex: Vertex) -> VertexOutput {
     out.face_index = vertex.face_index;
     out.normal = vertex.normal;
     out.uv = vertex.uv;
+    out.occlusion = vertex.occlusion;
     out.world_pos = vertex.position;
 
     return out;
@@ -49,13 +52,14 @@ struct FragmentInput {
     @location(1) normal: vec3<f32>,
     @location(2) uv: vec2<f32>,
     @location(3) face_index: u32,
+    @location(4) occlusion: f32,
 };
 
 struct FaceData {
     base_color: vec4<f32>,
 
-    base_texture: u32,
-    base_texture_uv: vec2<f32>,
+    uv: vec2<f32>,
+    uv_size: vec2<f32>,
 
     emissive_color: vec4<f32>,
 
@@ -66,6 +70,10 @@ struct FaceData {
 
 @group(1) @binding(0)
 var<storage> face_data: array<FaceData>;
+@group(1) @binding(1)
+var atlas_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var atlas_sampler: sampler;
 
 @fragment
 fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
@@ -73,6 +81,11 @@ fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
     var pbr_input: PbrInput;
 
     pbr_input.material.base_color = fd.base_color;
+    if (fd.uv_size.x > 0.0) {
+        // vertex UVs are in voxels, wrap them so textures repeat on merged faces
+        let atlas_uv = fd.uv + fract(in.uv) * fd.uv_size;
+        pbr_input.material.base_color *= textureSample(atlas_texture, atlas_sampler, atlas_uv);
+    }
     pbr_input.material.reflectance = fd.reflectance;
     //pbr_input.material.flags = fd.flags;
     //pbr_input.material.alpha_cutoff = fd.alpha_cutoff;
@@ -98,7 +111,8 @@ fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
     pbr_input.material.metallic = metallic;
     pbr_input.material.perceptual_roughness = perceptual_roughness;
 
-    var occlusion: f32 = 1.0;
+    // ambient occlusion baked into chunk vertices
+    var occlusion: f32 = in.occlusion;
     /*
     if (STANDARD_MATERIAL_FLAGS_OCCLUSION_TEXTURE_BIT != 0u) {
         occlusion = textureSample(p_occlusion_texture, p_occlusion_sampler, uv).r;
@@ -0,0 +1,171 @@
+use ahash::{HashMap, HashMapExt};
+
+use super::ChunkValueIndex;
+
+/// Sparse content is converted to dense content once more than
+/// `1 / SPARSE_MAX_RATIO` of voxels are non-empty.
+const SPARSE_MAX_RATIO: usize = 8;
+
+/// Value indices of all voxels in a [`ChunkStore`](super::ChunkStore).
+///
+/// Writes switch to a more general representation as needed, while
+/// [`optimize`](ChunkContent::optimize) picks the smallest one that can hold
+/// current content. Voxel indices passed to methods must be lower than the
+/// number of voxels in the store.
+#[derive(Debug, Clone)]
+pub enum ChunkContent {
+    /// All voxels have the same value index.
+    Uniform(ChunkValueIndex),
+    /// Value indices of non-empty voxels, for mostly empty chunks.
+    Sparse(HashMap<u32, ChunkValueIndex>),
+    /// Value index of every voxel.
+    Dense(Vec<ChunkValueIndex>),
+}
+
+impl Default for ChunkContent {
+    fn default() -> Self {
+        ChunkContent::Uniform(0)
+    }
+}
+
+impl ChunkContent {
+    /// Creates content from value indices of every voxel, using the smallest
+    /// representation.
+    pub fn from_dense(content: Vec<ChunkValueIndex>) -> ChunkContent {
+        let len = content.len();
+        let mut result = ChunkContent::Dense(content);
+        result.optimize(len);
+        result
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> ChunkValueIndex {
+        match self {
+            ChunkContent::Uniform(value) => *value,
+            ChunkContent::Sparse(values) => values.get(&(index as u32)).copied().unwrap_or(0),
+            ChunkContent::Dense(values) => values[index],
+        }
+    }
+
+    /// Sets value index of voxel `index` in content of `len` voxels.
+    pub fn set(&mut self, index: usize, value: ChunkValueIndex, len: usize) {
+        match self {
+            ChunkContent::Uniform(current) if *current == value => {}
+            ChunkContent::Uniform(0) => {
+                let mut values = HashMap::new();
+                values.insert(index as u32, value);
+                *self = ChunkContent::Sparse(values);
+            }
+            ChunkContent::Uniform(current) => {
+                let mut values = vec![*current; len];
+                values[index] = value;
+                *self = ChunkContent::Dense(values);
+            }
+            ChunkContent::Sparse(values) => {
+                if value == 0 {
+                    values.remove(&(index as u32));
+                    return;
+                }
+                values.insert(index as u32, value);
+                if values.len() * SPARSE_MAX_RATIO > len {
+                    self.make_dense(len);
+                }
+            }
+            ChunkContent::Dense(values) => values[index] = value,
+        }
+    }
+
+    /// Sets all voxels to `value`.
+    #[inline]
+    pub fn fill(&mut self, value: ChunkValueIndex) {
+        *self = ChunkContent::Uniform(value);
+    }
+
+    /// Converts content of `len` voxels into dense representation and
+    /// returns it.
+    pub fn make_dense(&mut self, len: usize) -> &mut Vec<ChunkValueIndex> {
+        if !matches!(self, ChunkContent::Dense(_)) {
+            *self = ChunkContent::Dense(self.iter(len).collect());
+        }
+        match self {
+            ChunkContent::Dense(values) => values,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns value indices of `len` voxels in order.
+    pub fn iter(&self, len: usize) -> impl Iterator<Item = ChunkValueIndex> + '_ {
+        (0..len).map(|index| self.get(index))
+    }
+
+    /// Switches to the smallest representation of content of `len` voxels.
+    pub fn optimize(&mut self, len: usize) {
+        if len == 0 {
+            *self = ChunkContent::Uniform(0);
+            return;
+        }
+
+        let first = self.get(0);
+        let mut uniform = true;
+        let mut non_empty = 0;
+        for value in self.iter(len) {
+            uniform &= value == first;
+            non_empty += (value != 0) as usize;
+        }
+
+        if uniform {
+            *self = ChunkContent::Uniform(first);
+        } else if non_empty * SPARSE_MAX_RATIO <= len {
+            if !matches!(self, ChunkContent::Sparse(_)) {
+                let values = self
+                    .iter(len)
+                    .enumerate()
+                    .filter(|(_, it)| *it != 0)
+                    .map(|(index, it)| (index as u32, it))
+                    .collect();
+                *self = ChunkContent::Sparse(values);
+            }
+        } else {
+            self.make_dense(len);
+        }
+    }
+
+    /// Marks value indices used by content of `len` voxels in `used`.
+    pub fn mark_used(&self, len: usize, used: &mut [bool]) {
+        match self {
+            ChunkContent::Uniform(value) => used[*value as usize] |= len > 0,
+            ChunkContent::Sparse(values) => {
+                used[0] |= values.len() < len;
+                for value in values.values() {
+                    used[*value as usize] = true;
+                }
+            }
+            ChunkContent::Dense(values) => {
+                for value in values {
+                    used[*value as usize] = true;
+                }
+            }
+        }
+    }
+
+    /// Replaces every value index `i` with `remap[i]`.
+    ///
+    /// Index `0` must map to itself as sparse content doesn't store empty
+    /// voxels.
+    pub fn remap(&mut self, remap: &[ChunkValueIndex]) {
+        debug_assert_eq!(remap[0], 0);
+        match self {
+            ChunkContent::Uniform(value) => *value = remap[*value as usize],
+            ChunkContent::Sparse(values) => {
+                for value in values.values_mut() {
+                    *value = remap[*value as usize];
+                }
+            }
+            ChunkContent::Dense(values) => {
+                for value in values {
+                    *value = remap[*value as usize];
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,392 @@
+//! Binary encoding of [`ChunkStore`] contents.
+//!
+//! Layout (all integers little endian):
+//!
+//! | field         | type                          |
+//! |---------------|-------------------------------|
+//! | magic         | `b"VXCS"`                     |
+//! | version       | `u8`                          |
+//! | size          | `[u32; 3]`                    |
+//! | palette size  | `u16`                         |
+//! | palette       | `palette size` × `u32` saved material ID |
+//! | index width   | `u8`                          |
+//! | content       | `size.x * size.y * size.z` indices, `index width` bits each |
+//!
+//! Content indices are packed starting from the least significant bit of each
+//! byte. Index width is the minimum number of bits required to represent every
+//! palette index (including `0` for empty voxels), so a chunk with only a
+//! single material stores 1 bit per voxel and an empty chunk stores none.
+//!
+//! Saved material IDs index the world [`MaterialTable`]. Version `1` stored
+//! palette entries as (`u16` length, UTF-8 [`MaterialID`] name) instead and is
+//! still readable.
+
+use std::io::{Read, Write};
+
+use bevy::prelude::*;
+
+use crate::error::StorageError;
+use crate::world::material::MaterialID;
+use crate::world::save::materials::MaterialTable;
+
+use super::{ChunkContent, ChunkStore, ChunkValueIndex, MAX_CHUNK_VALUES};
+
+const MAGIC: &[u8; 4] = b"VXCS";
+pub const FORMAT_VERSION: u8 = 2;
+/// Last version storing material names in palettes.
+const NAMED_PALETTE_VERSION: u8 = 1;
+/// Number of voxel indices reserved before reading content; chunk size is
+/// read from the file, so content of larger chunks grows as it's read.
+const MAX_RESERVED_VOXELS: usize = 64 * 64 * 64;
+
+/// Returns minimum number of bits required to store indices into a palette of
+/// `palette_len` entries.
+#[inline]
+pub const fn index_bit_width(palette_len: usize) -> u8 {
+    // 0 is reserved for empty voxels so largest index is equal to palette_len
+    (usize::BITS - palette_len.leading_zeros()) as u8
+}
+
+struct BitWriter<'w, W: Write> {
+    out: &'w mut W,
+    acc: u64,
+    acc_bits: u32,
+}
+
+impl<'w, W: Write> BitWriter<'w, W> {
+    fn new(out: &'w mut W) -> Self {
+        BitWriter {
+            out,
+            acc: 0,
+            acc_bits: 0,
+        }
+    }
+
+    fn push(&mut self, value: ChunkValueIndex, bits: u8) -> std::io::Result<()> {
+        self.acc |= (value as u64) << self.acc_bits;
+        self.acc_bits += bits as u32;
+        while self.acc_bits >= 8 {
+            self.out.write_all(&[self.acc as u8])?;
+            self.acc >>= 8;
+            self.acc_bits -= 8;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        if self.acc_bits > 0 {
+            self.out.write_all(&[self.acc as u8])?;
+        }
+        Ok(())
+    }
+}
+
+struct BitReader<'r, R: Read> {
+    input: &'r mut R,
+    acc: u64,
+    acc_bits: u32,
+}
+
+impl<'r, R: Read> BitReader<'r, R> {
+    fn new(input: &'r mut R) -> Self {
+        BitReader {
+            input,
+            acc: 0,
+            acc_bits: 0,
+        }
+    }
+
+    fn pull(&mut self, bits: u8) -> std::io::Result<ChunkValueIndex> {
+        while self.acc_bits < bits as u32 {
+            let mut byte = [0u8; 1];
+            self.input.read_exact(&mut byte)?;
+            self.acc |= (byte[0] as u64) << self.acc_bits;
+            self.acc_bits += 8;
+        }
+        let mask = (1u64 << bits) - 1;
+        let result = (self.acc & mask) as ChunkValueIndex;
+        self.acc >>= bits;
+        self.acc_bits -= bits as u32;
+        Ok(result)
+    }
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut result = [0u8; N];
+    input.read_exact(&mut result)?;
+    Ok(result)
+}
+
+impl ChunkStore<MaterialID> {
+    /// Writes binary representation of this store into `out`, adding
+    /// materials that weren't saved before to `table`.
+    ///
+    /// See [module documentation](self) for layout description.
+    pub fn write_to(
+        &self,
+        out: &mut impl Write,
+        table: &mut MaterialTable,
+    ) -> Result<(), StorageError> {
+        if self.values.len() > MAX_CHUNK_VALUES {
+            return Err(StorageError::PaletteOverflow(self.values.len()));
+        }
+
+        out.write_all(MAGIC)?;
+        out.write_all(&[FORMAT_VERSION])?;
+        for it in self.size.to_array() {
+            out.write_all(&it.to_le_bytes())?;
+        }
+
+        out.write_all(&(self.values.len() as u16).to_le_bytes())?;
+        for value in &self.values {
+            out.write_all(&table.saved_id(*value).to_le_bytes())?;
+        }
+
+        let bits = index_bit_width(self.values.len());
+        out.write_all(&[bits])?;
+        if bits == 0 {
+            return Ok(());
+        }
+
+        let mut writer = BitWriter::new(out);
+        for index in self.content.iter(self.len()) {
+            if index as usize > self.values.len() {
+                return Err(StorageError::InvalidContent(
+                    "value index out of palette bounds",
+                ));
+            }
+            writer.push(index, bits)?;
+        }
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    /// Reads a store previously written with [`ChunkStore::write_to`].
+    ///
+    /// Saved IDs missing from `table` are replaced with
+    /// [`MaterialID::MISSING`].
+    pub fn read_from(
+        input: &mut impl Read,
+        table: &MaterialTable,
+    ) -> Result<ChunkStore<MaterialID>, StorageError> {
+        if &read_array::<4>(input)? != MAGIC {
+            return Err(StorageError::InvalidHeader);
+        }
+        let [version] = read_array::<1>(input)?;
+        if version != FORMAT_VERSION && version != NAMED_PALETTE_VERSION {
+            return Err(StorageError::UnsupportedVersion(version));
+        }
+
+        let size = UVec3::new(
+            u32::from_le_bytes(read_array(input)?),
+            u32::from_le_bytes(read_array(input)?),
+            u32::from_le_bytes(read_array(input)?),
+        );
+        let length = (size.x as usize)
+            .checked_mul(size.y as usize)
+            .and_then(|it| it.checked_mul(size.z as usize))
+            .ok_or(StorageError::InvalidContent("chunk size overflow"))?;
+
+        let palette_len = u16::from_le_bytes(read_array(input)?) as usize;
+        if palette_len > MAX_CHUNK_VALUES {
+            return Err(StorageError::PaletteOverflow(palette_len));
+        }
+        let mut values = Vec::with_capacity(palette_len);
+        for _ in 0..palette_len {
+            if version == NAMED_PALETTE_VERSION {
+                let name_len = u16::from_le_bytes(read_array(input)?) as usize;
+                let mut name = vec![0u8; name_len];
+                input.read_exact(&mut name)?;
+                values.push(MaterialID::new(String::from_utf8(name)?));
+                continue;
+            }
+
+            let saved = u32::from_le_bytes(read_array(input)?);
+            values.push(table.material(saved).unwrap_or_else(|| {
+                tracing::warn!("Chunk refers to unknown saved material ID {}", saved);
+                MaterialID::MISSING
+            }));
+        }
+
+        let [bits] = read_array::<1>(input)?;
+        if bits != index_bit_width(palette_len) {
+            return Err(StorageError::InvalidContent(
+                "index width doesn't match palette size",
+            ));
+        }
+
+        let content = if bits == 0 {
+            ChunkContent::Uniform(0)
+        } else {
+            let mut content = Vec::with_capacity(length.min(MAX_RESERVED_VOXELS));
+            let mut reader = BitReader::new(input);
+            for _ in 0..length {
+                let index = reader.pull(bits)?;
+                if index as usize > palette_len {
+                    return Err(StorageError::InvalidContent(
+                        "value index out of palette bounds",
+                    ));
+                }
+                content.push(index);
+            }
+            ChunkContent::from_dense(content)
+        };
+
+        Ok(ChunkStore {
+            values,
+            size,
+            content,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::SizedGridMut;
+
+    fn material(i: usize) -> MaterialID {
+        MaterialID::new(format!("test:format_{}", i))
+    }
+
+    /// Returns a store of `size` using `palette_len` materials, all of which
+    /// are placed at least once if the store is large enough.
+    fn store(size: UVec3, palette_len: usize) -> ChunkStore<MaterialID> {
+        let mut result = ChunkStore::new(size);
+        result.values = (0..palette_len).map(material).collect();
+        let mut i = 0;
+        for y in 0..size.y {
+            for z in 0..size.z {
+                for x in 0..size.x {
+                    result.set_pos_id(UVec3::new(x, y, z), (i % (palette_len + 1)) as u16);
+                    i += 1;
+                }
+            }
+        }
+        result
+    }
+
+    fn round_trip(store: &ChunkStore<MaterialID>) -> ChunkStore<MaterialID> {
+        let mut table = MaterialTable::default();
+        let mut data = Vec::new();
+        store.write_to(&mut data, &mut table).unwrap();
+        ChunkStore::read_from(&mut data.as_slice(), &table).unwrap()
+    }
+
+    fn assert_same(a: &ChunkStore<MaterialID>, b: &ChunkStore<MaterialID>) {
+        assert_eq!(a.size, b.size);
+        assert_eq!(a.values, b.values);
+        assert!(a.content.iter(a.len()).eq(b.content.iter(b.len())));
+    }
+
+    #[test]
+    fn empty_store_round_trip() {
+        let empty = ChunkStore::new(UVec3::new(4, 5, 6));
+        let result = round_trip(&empty);
+        assert_same(&empty, &result);
+        assert!(matches!(result.content, ChunkContent::Uniform(0)));
+    }
+
+    #[test]
+    fn single_material_round_trip() {
+        let mut single = ChunkStore::new(UVec3::splat(4));
+        single.values.push(material(0));
+        single.fill(1);
+        assert_same(&single, &round_trip(&single));
+    }
+
+    #[test]
+    fn palette_bit_width_boundaries_round_trip() {
+        for (palette_len, bits) in [(1, 1), (2, 2), (3, 2), (255, 8), (256, 9)] {
+            assert_eq!(index_bit_width(palette_len), bits);
+            let store = store(UVec3::new(8, 5, 7), palette_len);
+            assert_same(&store, &round_trip(&store));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut data = Vec::new();
+        store(UVec3::ONE, 1)
+            .write_to(&mut data, &mut MaterialTable::default())
+            .unwrap();
+        data[0] = b'X';
+        let result = ChunkStore::read_from(&mut data.as_slice(), &MaterialTable::default());
+        assert!(matches!(result, Err(StorageError::InvalidHeader)));
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut data = Vec::new();
+        store(UVec3::ONE, 1)
+            .write_to(&mut data, &mut MaterialTable::default())
+            .unwrap();
+        data[MAGIC.len()] = FORMAT_VERSION + 1;
+        let result = ChunkStore::read_from(&mut data.as_slice(), &MaterialTable::default());
+        assert!(matches!(
+            result,
+            Err(StorageError::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let mut table = MaterialTable::default();
+        let mut data = Vec::new();
+        store(UVec3::splat(4), 3)
+            .write_to(&mut data, &mut table)
+            .unwrap();
+        for len in [0, 3, MAGIC.len() + 1, 12, data.len() - 1] {
+            let result = ChunkStore::read_from(&mut &data[..len], &table);
+            assert!(
+                matches!(result, Err(StorageError::Io(_))),
+                "{} bytes were accepted",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn reads_named_palette_version() {
+        let names = ["test:format_named_a", "test:format_named_b"];
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.push(NAMED_PALETTE_VERSION);
+        for it in [2u32, 1, 1] {
+            data.extend_from_slice(&it.to_le_bytes());
+        }
+        data.extend_from_slice(&(names.len() as u16).to_le_bytes());
+        for name in names {
+            data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+        }
+        // two 2-bit indices: 2 then 1
+        data.push(2);
+        data.push(0b01_10);
+
+        let result =
+            ChunkStore::read_from(&mut data.as_slice(), &MaterialTable::default()).unwrap();
+        assert_eq!(result.size, UVec3::new(2, 1, 1));
+        assert_eq!(result.values, names.map(MaterialID::new).to_vec());
+        assert!(result.content.iter(2).eq([2, 1]));
+    }
+
+    #[test]
+    fn rejects_oversized_content() {
+        // header claims 2^40 voxels, but only a single content byte follows
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.push(FORMAT_VERSION);
+        for it in [1u32 << 16, 1 << 16, 1 << 8] {
+            data.extend_from_slice(&it.to_le_bytes());
+        }
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.push(index_bit_width(1));
+        data.push(0xFF);
+
+        let result = ChunkStore::read_from(&mut data.as_slice(), &MaterialTable::default());
+        assert!(matches!(result, Err(StorageError::Io(_))));
+    }
+}
@@ -1,29 +1,19 @@
-use std::future::Future;
 use std::hash::{Hash, Hasher};
-use std::marker::PhantomData;
-use std::ops::Index;
-use std::sync::Arc;
 
-use bevy::{prelude::*, render::render_asset::RenderAssetUsages};
 use bevy::render::mesh::*;
+use bevy::{prelude::*, render::render_asset::RenderAssetUsages};
 use indexmap::IndexSet;
-use maybe_owned::MaybeOwned;
-use ndarray::{Array, Slice};
-use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
-use rayon::prelude::*;
-
-use crate::data::{FaceProperties, LoadedMaterials};
 use crate::data::MaterialProperties;
-use crate::MaterialID;
+use crate::data::{FaceProperties, LoadedMaterials};
+use crate::math::axis::WorldAxis;
+use crate::math::pos::ChunkPos;
 use crate::math::side::Side;
-use crate::world::chunk::ChunkStore;
+use crate::world::chunk::{ChunkStore, ChunkValueIndex, Mesher};
+use crate::MaterialID;
 
-use super::{
-    chunk_material::ChunkMaterial, SIDE_VIEW_TRANSFORMS, SideView, SizedGrid, SliceView,
-    wrapped_rows,
-};
+use super::{chunk_material::ChunkMaterial, SideView, SizedGrid, SliceView};
 
 pub fn visible_chunk_sides(player_pos: Vec3, chunk_pos: Vec3) -> [Side; 3] {
     [
@@ -45,72 +35,74 @@ pub fn visible_chunk_sides(player_pos: Vec3, chunk_pos: Vec3) -> [Side; 3] {
     ]
 }
 
-static FLIP_OPPOSITE_TRANSFORMS: Lazy<[(Mat3, Vec3); 6]> = Lazy::new(|| {
-    Side::ALL
-        .map(|it| {
-            SIDE_VIEW_TRANSFORMS[it.opposite() as usize].0
-                * SIDE_VIEW_TRANSFORMS[it as usize].0.inverse()
-        })
-        .map(wrapped_rows)
-});
-const SIDE_SIZE: f32 = 1.0;
-
-fn face_from_side_pos(side: Side, position: UVec3, size: IVec2) -> [Vec3; 4] {
-    let normal = side.normal();
-    let position = position.as_vec3() + normal * (SIDE_SIZE / 2.);
-
-    // FIXME: Test opposite case at the opposite ends - 80% says it's wrong
-    [
-        position,
-        position + (size.as_vec2() * Vec2::new(1., 0.)).extend(0.) * SIDE_SIZE,
-        position + (size.as_vec2() * Vec2::new(1., 1.)).extend(0.) * SIDE_SIZE,
-        position + (size.as_vec2() * Vec2::new(0., 1.)).extend(0.) * SIDE_SIZE,
-    ]
-}
-
-fn opposite_face_from_side_pos(
-    side: Side,
-    position: UVec3,
-    chunk_size: UVec3,
-    size: IVec2,
-) -> [Vec3; 4] {
-    // FIXME: Normalize to lower octant coordinates.
-    let (t_mul, t_add) = FLIP_OPPOSITE_TRANSFORMS[side as usize];
-    let position = t_mul * position.as_vec3() + t_add * (chunk_size - UVec3::ONE).as_vec3();
-    let size = (t_add.as_ivec3() * -1).xy() * size; // FIXME: blind error fix
-    return face_from_side_pos(side, position.as_uvec3(), size);
-}
-
-#[derive(Debug, Clone)]
+/// Quad covering one or more voxel faces.
+///
+/// Corners are stored in chunk-local voxel space (voxel at `p` spans from `p`
+/// to `p + 1`) in `[origin, origin + U, origin + V, origin + U + V]` order,
+/// where `U × V` points out of the voxel.
+#[derive(Debug, Clone, PartialEq)]
 pub struct MeshFace {
-    corners: [Vec3; 4],
+    pub corners: [UVec3; 4],
 }
 
 impl MeshFace {
-    pub fn new(side: Side, position: UVec3, size: IVec2) -> Self {
-        MeshFace {
-            corners: face_from_side_pos(side, position, size),
+    /// Creates a face on `side` of voxel at `position`, spanning `size` voxels
+    /// along the [slice plane](WorldAxis::slice_plane) of the side axis.
+    pub fn new(side: Side, position: UVec3, size: UVec2) -> Self {
+        let axis = side.axis();
+        let [u, v] = axis.slice_plane();
+
+        let mut origin = position;
+        if !side.is_negative() {
+            origin[axis] += 1;
         }
-    }
+        let mut du = UVec3::ZERO;
+        du[u] = size.x;
+        let mut dv = UVec3::ZERO;
+        dv[v] = size.y;
+
+        // slice plane axes of Y are ordered (X, Z) which points U × V down
+        let (du, dv) = if (axis == WorldAxis::Y) == side.is_negative() {
+            (du, dv)
+        } else {
+            (dv, du)
+        };
 
-    pub fn new_opposite(side: Side, position: UVec3, chunk_size: UVec3, size: IVec2) -> Self {
         MeshFace {
-            corners: opposite_face_from_side_pos(side, position, chunk_size, size),
+            corners: [origin, origin + du, origin + dv, origin + du + dv],
         }
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct FaceInfo<'a> {
-    face: MeshFace,
-    material: &'a MaterialProperties,
+pub struct FaceInfo {
+    pub face: MeshFace,
+    pub side: Side,
+    /// Chunk store index of the value this face belongs to.
+    pub value: ChunkValueIndex,
+    /// Ambient occlusion level of each corner, ranging from `0` (fully
+    /// occluded) to `3` (unoccluded).
+    pub occlusion: [u8; 4],
+}
+
+/// Number of chunks in a 3×3×3 block around (and including) a chunk.
+const NEIGHBOURHOOD_SIZE: usize = 27;
+
+/// Returns index of the neighbour at `offset` in arrays of
+/// [`NEIGHBOURHOOD_SIZE`] elements.
+#[inline]
+fn neighbour_index(offset: IVec3) -> usize {
+    let it = offset + IVec3::ONE;
+    (it.x + it.y * 3 + it.z * 9) as usize
 }
 
 pub struct MeshingContext<'a, T = MaterialID>
 where
     T: PartialEq,
 {
-    sides: [Option<&'a ChunkStore<T>>; Side::COUNT],
+    /// Chunks sharing a face, an edge or a corner with the meshed chunk,
+    /// indexed by [`neighbour_index`].
+    neighbours: [Option<&'a ChunkStore<T>>; NEIGHBOURHOOD_SIZE],
 }
 
 type ContextWindow<'a, T> = Option<SliceView<'a, T, SideView<'a, T, ChunkStore<T>>>>;
@@ -119,24 +111,92 @@ impl<'a, T> MeshingContext<'a, T>
 where
     T: PartialEq,
 {
+    /// Creates context with only the neighbours sharing a face with the
+    /// meshed chunk.
+    pub fn new(sides: [Option<&'a ChunkStore<T>>; Side::COUNT]) -> Self {
+        let mut result = MeshingContext::empty();
+        for side in Side::ALL {
+            result.neighbours[neighbour_index(side.direction_ivec3())] = sides[side];
+        }
+        result
+    }
+
+    /// Creates context with neighbours at every [neighbour
+    /// offset](ChunkPos::neighbour_offsets) returned by `neighbour`.
+    ///
+    /// Edge and corner neighbours are only used for ambient occlusion.
+    pub fn from_fn(mut neighbour: impl FnMut(IVec3) -> Option<&'a ChunkStore<T>>) -> Self {
+        let mut result = MeshingContext::empty();
+        for offset in ChunkPos::neighbour_offsets() {
+            result.neighbours[neighbour_index(offset)] = neighbour(offset);
+        }
+        result
+    }
+
+    /// Context without any neighbours; all border faces will be emitted.
+    pub fn empty() -> Self {
+        MeshingContext {
+            neighbours: [None; NEIGHBOURHOOD_SIZE],
+        }
+    }
+
+    /// Returns neighbour at `offset` (in chunks) from the meshed chunk.
+    pub fn neighbour(&self, offset: IVec3) -> Option<&'a ChunkStore<T>> {
+        if offset == IVec3::ZERO || offset.abs().max_element() > 1 {
+            return None;
+        }
+        self.neighbours[neighbour_index(offset)]
+    }
+
     pub fn has(&self, side: Side) -> bool {
-        self.sides[side].is_some()
+        self.neighbour(side.direction_ivec3()).is_some()
     }
 
     pub fn get(&self, side: Side) -> ContextWindow<'a, T> {
-        let v = self.sides[side]?;
+        let v = self.neighbour(side.direction_ivec3())?;
         Some(SliceView::new(SideView::new(v, side.opposite()), 0))
     }
 
     pub fn get_entry(&self, side: Side, pos: UVec2) -> Option<&'a T> {
         self.get(side).and_then(|it| it.get_pos_value(pos))
     }
+
+    /// Returns value adjacent to `pos` on the `side` border of the meshed
+    /// chunk, i.e. the value stored in the neighbouring chunk.
+    ///
+    /// Neighbours are expected to have the same size as the meshed chunk.
+    pub fn neighbour_value(&self, side: Side, pos: UVec3) -> Option<&'a T> {
+        let neighbour = self.neighbour(side.direction_ivec3())?;
+        let axis = side.axis();
+        let mut pos = pos;
+        pos[axis] = if side.is_negative() {
+            neighbour.size[axis] - 1
+        } else {
+            0
+        };
+        neighbour.value_of_index(neighbour.get_pos_key(pos)?)
+    }
+
+    /// Returns value at `pos` relative to the meshed chunk of `size`, which
+    /// lies in one of its neighbours.
+    ///
+    /// Neighbours are expected to have the same size as the meshed chunk.
+    pub fn value_at(&self, pos: IVec3, size: UVec3) -> Option<&'a T> {
+        let size = size.as_ivec3();
+        let neighbour = self.neighbour(pos.div_euclid(size))?;
+        let local = pos.rem_euclid(size).as_uvec3();
+        neighbour.value_of_index(neighbour.get_pos_key(local)?)
+    }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, Component)]
 pub struct MeshingState {
-    /// Chunk sides that were provided context and have been meshed.
-    meshed_sides: [bool; Side::COUNT],
+    /// Neighbours that were provided as context when the chunk was meshed,
+    /// indexed by [`neighbour_index`].
+    meshed_neighbours: [bool; NEIGHBOURHOOD_SIZE],
+    /// Neighbours whose adjacent border voxels need to be re-meshed, indexed
+    /// by [`neighbour_index`].
+    dirty_neighbours: [bool; NEIGHBOURHOOD_SIZE],
     /// Whether the insides of the chunk has been meshed.
     meshed_internals: bool,
     /// List of locations that have changed since the cached mesh has been generated.
@@ -144,461 +204,553 @@ pub struct MeshingState {
 }
 
 impl MeshingState {
+    /// Records that the voxel at `location` has changed.
+    ///
+    /// Changes touching chunk borders also affect faces of neighbouring chunks,
+    /// use [`MeshingState::touched_neighbours`] to find which neighbours need
+    /// to mark their voxels nearest to the change dirty.
     pub fn mark_dirty(&mut self, location: UVec3) {
-        todo!()
+        if !self.meshed_internals {
+            // whole chunk will be meshed anyway
+            return;
+        }
+        if !self.invalidated.contains(&location) {
+            self.invalidated.push(location);
+        }
     }
-}
 
-// When first loaded you need to mesh inner as well as 1/2 margins
-// Then update state
-// When signalled that surroundings changed, state needs to me read and updated after mesing newly available margins
-// When signalled internals changed, ...
-//  - Don't update for individual changes, use invalidated instead. This complicated, do it last if even
-//
+    /// Marks border voxels adjacent to the neighbour at `offset` (in chunks)
+    /// for re-meshing.
+    ///
+    /// Faces of those voxels depend on the neighbour through culling and
+    /// ambient occlusion. Besides the border layers along axes the neighbour
+    /// is offset on, this re-meshes every layer of the remaining axes, as
+    /// border voxels have faces in all of them.
+    #[inline]
+    pub fn invalidate_neighbour(&mut self, offset: IVec3) {
+        self.dirty_neighbours[neighbour_index(offset)] = true;
+    }
 
-pub async fn mesh_inner_slice<'a, 'b, G: SizedGrid<'a, MaterialID>>(
-    blocks: &'b G,
-    loaded: &'a LoadedMaterials,
-    depth: u32,
-    top: &'b Option<SliceView<'a, MaterialID, G>>,
-    bottom: &'b Option<SliceView<'a, MaterialID, G>>,
-) -> Vec<FaceInfo<'a>>
-where
-    'a: 'b,
-    MaybeOwned<'b, G>: From<&'b G>,
-{
-    let mut result = Vec::with_capacity(4);
+    /// Notifies the state that a neighbour at `offset` became available.
+    ///
+    /// Border voxels are only re-meshed if they were meshed without the
+    /// neighbour, so that hidden border faces can be culled and occlusion
+    /// from the neighbour picked up.
+    #[inline]
+    pub fn neighbour_loaded(&mut self, offset: IVec3) {
+        if !self.meshed_neighbours[neighbour_index(offset)] {
+            self.invalidate_neighbour(offset);
+        }
+    }
 
-    let mut ids_before = SliceView::try_new(blocks, depth as i32 - 1).or_else(|| *top);
-    let mut ids_at = SliceView::new(blocks, depth);
-    let mut ids_after = SliceView::try_new(blocks, depth as i32 + 1).or_else(|| *bottom);
+    /// Notifies the state that the neighbour at `offset` was unloaded.
+    ///
+    /// Border faces culled against it have to be emitted again.
+    #[inline]
+    pub fn neighbour_unloaded(&mut self, offset: IVec3) {
+        if self.meshed_neighbours[neighbour_index(offset)] {
+            self.invalidate_neighbour(offset);
+        }
+    }
 
-    let mut front_faces = Vec::new();
-    let mut back_faces = Vec::new();
+    #[inline]
+    pub fn is_neighbour_meshed(&self, offset: IVec3) -> bool {
+        self.meshed_neighbours[neighbour_index(offset)]
+    }
 
-    for x in 0..blocks.size().x as usize {
-        let mut y = 0;
-        while y < blocks.size().y as usize {
-            let pos = UVec2::new(x as u32, y as u32);
-            let current = match ids_at.get_pos_value(pos) {
-                Some(it) => it,
-                None => {
-                    consumed[(x, y, 0)] = depth;
-                    consumed[(x, y, 1)] = depth;
-                    continue;
-                }
-            };
-            let material = {
-                #[cfg(debug_assertions)]
-                {
-                    loaded
-                        .properties
-                        .get(current)
-                        .expect(format!("material registry missing id: {}", current).as_str())
-                }
-                #[cfg(not(debug_assertions))]
-                unsafe {
-                    loaded.properties.get(current).unwrap_unchecked()
-                }
-            };
+    /// Discards all cached state, causing the whole chunk to be re-meshed.
+    pub fn invalidate_all(&mut self) {
+        *self = MeshingState::default();
+    }
 
-            let check_side = |side: Option<SliceView<'_, MaterialID, _>>| {
-                let mut h = 1;
-                let mut w = 1;
+    #[inline]
+    pub fn is_dirty(&self) -> bool {
+        !self.meshed_internals
+            || !self.invalidated.is_empty()
+            || self.dirty_neighbours.contains(&true)
+    }
 
-                let mut pos = |h, w| UVec2::new(x as u32 + h, y as u32 + w);
+    /// Returns offsets of neighbouring chunks (sharing a face, an edge or a
+    /// corner) a change at `location` is adjacent to.
+    pub fn touched_neighbours(location: UVec3, size: UVec3) -> impl Iterator<Item = IVec3> {
+        ChunkPos::neighbour_offsets().filter(move |offset| {
+            WorldAxis::ALL.into_iter().all(|axis| match offset[axis] {
+                -1 => location[axis] == 0,
+                1 => location[axis] + 1 == size[axis],
+                _ => true,
+            })
+        })
+    }
 
-                while ids_at.get_pos_value(pos(h, 0)) == Some(current) {
-                    let above = side.and_then(|it| it.get_pos_value(pos(h, 0)));
-                    if !is_block_face_visible(above, current, material, loaded) {
-                        break;
-                    }
-                    h += 1;
-                }
-                'outer: loop {
-                    for ih in 0..h {
-                        if ids_at.get_pos_value(pos(ih, w)) != Some(current) {
-                            break 'outer;
-                        }
-                        let above = side.and_then(|it| it.get_pos_value(pos(ih, w)));
+    /// Returns layers along each axis that need to be re-meshed.
+    ///
+    /// Change of a single voxel affects its own faces and faces of its direct
+    /// neighbours facing it, so for every axis the layer containing the voxel
+    /// and both adjacent layers are re-meshed.
+    fn dirty_layers(&self, size: UVec3) -> [Vec<u32>; 3] {
+        if !self.meshed_internals {
+            return WorldAxis::ALL.map(|axis| (0..size[axis]).collect());
+        }
 
-                        if !is_block_face_visible(above, current, material, loaded) {
-                            break 'outer;
-                        }
-                    }
-                    w += 1;
+        let mut result = WorldAxis::ALL.map(|_| Vec::with_capacity(self.invalidated.len() * 3));
+        for location in &self.invalidated {
+            for axis in WorldAxis::ALL {
+                let depth = location[axis];
+                let layers = &mut result[axis as usize];
+                layers.extend(depth.checked_sub(1));
+                layers.push(depth);
+                if depth + 1 < size[axis] {
+                    layers.push(depth + 1);
                 }
+            }
+        }
+        for offset in ChunkPos::neighbour_offsets() {
+            if !self.dirty_neighbours[neighbour_index(offset)] {
+                continue;
+            }
+            for axis in WorldAxis::ALL {
+                let layers = &mut result[axis as usize];
+                match offset[axis] {
+                    -1 => layers.push(0),
+                    1 => layers.push(size[axis].saturating_sub(1)),
+                    _ => layers.extend(0..size[axis]),
+                }
+            }
+        }
 
-                return IVec2::new(w as i32, h as i32);
-            };
-
-            let mut min_y = 1;
-
-            // top face
-            if consumed[(x, y, 0)] != depth {
-                let above = ids_before.and_then(|it| it.get_pos_value(pos));
+        for layers in &mut result {
+            layers.sort_unstable();
+            layers.dedup();
+        }
+        result
+    }
+}
 
-                if is_block_face_visible(above, current, material, loaded) {
-                    let size = check_side(ids_before);
+/// Faces generated for each layer of a chunk, used to avoid re-meshing layers
+/// that haven't changed.
+#[derive(Debug, Default, Component)]
+pub struct MeshCache {
+    layers: [Vec<Vec<FaceInfo>>; 3],
+}
 
-                    front_faces.push(FaceInfo::new(
-                        side,
-                        UVec3::new(x as u32, y as u32, depth as u32),
-                        size,
-                        material,
-                    ));
+impl MeshCache {
+    /// Re-meshes layers invalidated in `state` with `mesh_layer` and reuses
+    /// cached faces for the rest.
+    ///
+    /// `mesh_layer` is called with the sweep axis and depth of the layer and
+    /// must return faces of voxels in that layer facing along the axis (in
+    /// both directions). Border faces on sides for which `context` has no
+    /// neighbour are kept as if the neighbour were empty; missing neighbours
+    /// are remembered so those faces can be culled once the neighbour is
+    /// [loaded](MeshingState::neighbour_loaded).
+    pub fn update<T: PartialEq>(
+        &mut self,
+        state: &mut MeshingState,
+        context: &MeshingContext<T>,
+        size: UVec3,
+        mut mesh_layer: impl FnMut(WorldAxis, u32) -> Vec<FaceInfo>,
+    ) {
+        for axis in WorldAxis::ALL {
+            if self.layers[axis as usize].len() != size[axis] as usize {
+                self.layers[axis as usize] = vec![Vec::new(); size[axis] as usize];
+                state.meshed_internals = false;
+            }
+        }
 
-                    min_y = std::cmp::min(min_y, size.y);
-                }
+        for (axis, layers) in WorldAxis::ALL.into_iter().zip(state.dirty_layers(size)) {
+            for depth in layers {
+                self.layers[axis as usize][depth as usize] = mesh_layer(axis, depth);
             }
+        }
 
-            // bottom face
-            if consumed[(x, y, 1)] != depth {
-                let above = ids_after.and_then(|it| it.get_pos_value(pos));
+        state.invalidated.clear();
+        state.meshed_internals = true;
+        state.dirty_neighbours = [false; NEIGHBOURHOOD_SIZE];
+        state.meshed_neighbours = context.neighbours.map(|it| it.is_some());
+    }
 
-                if is_block_face_visible(above, current, material, loaded) {
-                    let size = check_side(ids_after);
+    pub fn faces(&self) -> impl Iterator<Item = &FaceInfo> {
+        self.layers.iter().flatten().flatten()
+    }
 
-                    back_faces.push(FaceInfo::new_opposite(
-                        side,
-                        UVec3::new(x as u32, y as u32, depth as u32),
-                        blocks.size(),
-                        size,
-                        material,
-                    ));
+    pub fn face_count(&self) -> usize {
+        self.layers.iter().flatten().map(Vec::len).sum()
+    }
+}
 
-                    min_y = std::cmp::min(min_y, size.y);
-                }
+impl Mesher {
+    /// Meshes a single layer of `grid` perpendicular to `axis`.
+    ///
+    /// Produces faces of voxels at `depth` that face along `axis` in either
+    /// direction; faces on the chunk border are culled against neighbours
+    /// provided by `context`.
+    pub fn mesh_layer<'d, G: SizedGrid<'d, MaterialID>>(
+        &self,
+        grid: &G,
+        context: &MeshingContext,
+        loaded: &LoadedMaterials,
+        axis: WorldAxis,
+        depth: u32,
+    ) -> Vec<FaceInfo> {
+        match self {
+            Mesher::Greedy => greedy_mesh_layer(grid, context, loaded, axis, depth),
+            Mesher::Naive => {
+                per_face_mesh_layer(grid, &MeshingContext::empty(), loaded, axis, depth)
             }
-
-            y += min_y as usize;
+            Mesher::Culled => per_face_mesh_layer(grid, context, loaded, axis, depth),
         }
     }
 
-    [front_faces, back_faces]
+    /// Meshes all layers of `grid`.
+    pub fn mesh<'d, G: SizedGrid<'d, MaterialID>>(
+        &self,
+        grid: &G,
+        context: &MeshingContext,
+        loaded: &LoadedMaterials,
+    ) -> Vec<FaceInfo> {
+        let size = grid.size();
+        WorldAxis::ALL
+            .into_iter()
+            .flat_map(|axis| {
+                (0..size[axis]).flat_map(move |depth| {
+                    self.mesh_layer(grid, context, loaded, axis, depth)
+                })
+            })
+            .collect()
+    }
 }
 
-pub fn mesh_side<'a, G: SizedGrid<'a, MaterialID>>(
-    blocks: &G,
-    loaded: &'a LoadedMaterials,
+/// Returns value of the voxel adjacent to `pos` in `side` direction, looking
+/// into neighbouring chunks through `context` for voxels on the chunk border.
+fn adjacent_value<'r, 'd: 'r, G: SizedGrid<'d, MaterialID>>(
+    grid: &G,
+    context: &MeshingContext<'r>,
+    pos: UVec3,
     side: Side,
-) -> Vec<FaceInfo<'a>> {
-    let blocks = SideView::new(blocks, side);
-
-    let mut consumed =
-        Array::from_elem((blocks.size().x as usize, blocks.size().y as usize, 2), -1);
-
-    (0..blocks.size().z as i32)
-        .into_par_iter()
-        .map(|depth| {
-            let mut ids_before = SliceView::try_new(&blocks, depth - 1);
-            let mut ids_at = SliceView::new(&blocks, depth as u32);
-            let mut ids_after = SliceView::try_new(&blocks, depth + 1);
-
-            let mut front_faces = Vec::new();
-            let mut back_faces = Vec::new();
-
-            for x in 0..blocks.size().x as usize {
-                let mut y = 0;
-                while y < blocks.size().y as usize {
-                    let pos = UVec2::new(x as u32, y as u32);
-                    let current = match ids_at.get_pos_value(pos) {
-                        Some(it) => it,
-                        None => {
-                            consumed[(x, y, 0)] = depth;
-                            consumed[(x, y, 1)] = depth;
-                            continue;
-                        }
-                    };
-                    let material = {
-                        #[cfg(debug_assertions)]
-                        {
-                            loaded.properties.get(current).expect(
-                                format!("material registry missing id: {}", current).as_str(),
-                            )
-                        }
-                        #[cfg(not(debug_assertions))]
-                        {
-                            loaded.properties.get(current).unwrap_unchecked()
-                        }
-                    };
+) -> Option<&'r MaterialID> {
+    let axis = side.axis();
+    let at = pos[axis];
+    let on_border = if side.is_negative() {
+        at == 0
+    } else {
+        at + 1 == grid.size()[axis]
+    };
+    if on_border {
+        return context.neighbour_value(side, pos);
+    }
 
-                    let check_side = |side: Option<SliceView<'_, MaterialID, _>>| {
-                        let mut h = 1;
-                        let mut w = 1;
+    let mut other = pos;
+    other[axis] = if side.is_negative() { at - 1 } else { at + 1 };
+    grid.value_of_index(grid.get_pos_key(other)?)
+}
 
-                        let mut pos = |h, w| UVec2::new(x as u32 + h, y as u32 + w);
+/// Returns whether voxel at `pos` darkens corners of faces next to it.
+///
+/// `pos` is relative to the meshed chunk and may lie in any neighbouring
+/// chunk provided by `context`, including ones across chunk edges and
+/// corners. Voxels in missing neighbours never occlude.
+fn is_occluder<'d, G: SizedGrid<'d, MaterialID>>(
+    grid: &G,
+    context: &MeshingContext,
+    loaded: &LoadedMaterials,
+    materials: &[Option<&MaterialProperties>],
+    pos: IVec3,
+) -> bool {
+    let size = grid.size();
+    let inside = pos.cmpge(IVec3::ZERO).all() && pos.cmplt(size.as_ivec3()).all();
 
-                        while ids_at.get_pos_value(pos(h, 0)) == Some(current) {
-                            let above = side.and_then(|it| it.get_pos_value(pos(h, 0)));
-                            if !is_block_face_visible(above, current, material, loaded) {
-                                break;
-                            }
-                            h += 1;
-                        }
-                        'outer: loop {
-                            for ih in 0..h {
-                                if ids_at.get_pos_value(pos(ih, w)) != Some(current) {
-                                    break 'outer;
-                                }
-                                let above = side.and_then(|it| it.get_pos_value(pos(ih, w)));
-
-                                if !is_block_face_visible(above, current, material, loaded) {
-                                    break 'outer;
-                                }
-                            }
-                            w += 1;
-                        }
+    let material = if inside {
+        match grid.get_pos_key(pos.as_uvec3()) {
+            Some(0) | None => return false,
+            Some(key) => materials[key as usize - 1],
+        }
+    } else {
+        match context.value_at(pos, size) {
+            Some(id) => loaded.get(id),
+            None => return false,
+        }
+    };
+    // voxels without material properties are treated as opaque
+    material.map(|it| it.color().w >= 1.0).unwrap_or(true)
+}
 
-                        return IVec2::new(w as i32, h as i32);
-                    };
+/// Computes ambient occlusion level of every corner of `face` covering `side`
+/// of a single voxel at `pos`.
+///
+/// Each corner is darkened by the two voxels sharing an edge with it and the
+/// one sharing only the corner, all in the layer in front of the face.
+fn face_occlusion(
+    face: &MeshFace,
+    pos: UVec3,
+    side: Side,
+    occludes: impl Fn(IVec3) -> bool,
+) -> [u8; 4] {
+    let [u_axis, v_axis] = side.axis().slice_plane();
+    let front = pos.as_ivec3() + side.direction_ivec3();
+
+    face.corners.map(|corner| {
+        let mut du = IVec3::ZERO;
+        du[u_axis] = if corner[u_axis] > pos[u_axis] { 1 } else { -1 };
+        let mut dv = IVec3::ZERO;
+        dv[v_axis] = if corner[v_axis] > pos[v_axis] { 1 } else { -1 };
+
+        let edge_u = occludes(front + du);
+        let edge_v = occludes(front + dv);
+        if edge_u && edge_v {
+            0
+        } else {
+            3 - edge_u as u8 - edge_v as u8 - occludes(front + du + dv) as u8
+        }
+    })
+}
 
-                    let mut min_y = 1;
+/// Visible face in a [layer mask](visible_face_mask).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MaskCell {
+    value: ChunkValueIndex,
+    occlusion: [u8; 4],
+}
 
-                    // top face
-                    if consumed[(x, y, 0)] != depth {
-                        let above = ids_before.and_then(|it| it.get_pos_value(pos));
+/// Builds a mask of visible faces in a layer.
+///
+/// Mask is indexed by `u + v * size_u` where `(u, v)` are coordinates along
+/// the slice plane of the layer axis and contains value index and corner
+/// occlusion of the voxel whose face is visible at that location.
+fn visible_face_mask<'d, G: SizedGrid<'d, MaterialID>>(
+    grid: &G,
+    context: &MeshingContext,
+    loaded: &LoadedMaterials,
+    side: Side,
+    depth: u32,
+) -> (UVec2, Vec<Option<MaskCell>>) {
+    let size = grid.size();
+    let axis = side.axis();
+    let [u_axis, v_axis] = axis.slice_plane();
+    let mask_size = UVec2::new(size[u_axis], size[v_axis]);
+
+    let values = grid.values();
+    let materials: Vec<Option<&MaterialProperties>> =
+        values.iter().map(|it| loaded.get(it)).collect();
+
+    let mut mask = vec![None; mask_size.x as usize * mask_size.y as usize];
+    for v in 0..mask_size.y {
+        for u in 0..mask_size.x {
+            let mut pos = UVec3::ZERO;
+            pos[axis] = depth;
+            pos[u_axis] = u;
+            pos[v_axis] = v;
+
+            let key = match grid.get_pos_key(pos) {
+                Some(0) | None => continue,
+                Some(it) => it,
+            };
+            let current = values[key as usize - 1];
+            let current_mat = materials[key as usize - 1];
+
+            let above = adjacent_value(grid, context, pos, side);
+            let above_mat = above.and_then(|it| loaded.get(it));
+
+            if is_block_face_visible(above, above_mat, current, current_mat) {
+                let occlusion =
+                    face_occlusion(&MeshFace::new(side, pos, UVec2::ONE), pos, side, |it| {
+                        is_occluder(grid, context, loaded, &materials, it)
+                    });
+                mask[(u + v * mask_size.x) as usize] = Some(MaskCell {
+                    value: key,
+                    occlusion,
+                });
+            }
+        }
+    }
 
-                        if is_block_face_visible(above, current, material, loaded) {
-                            let size = check_side(ids_before);
+    (mask_size, mask)
+}
 
-                            front_faces.push(FaceInfo::new(
-                                side,
-                                UVec3::new(x as u32, y as u32, depth as u32),
-                                size,
-                                material,
-                            ));
+/// Emits a single quad for every visible face of a layer.
+pub fn per_face_mesh_layer<'d, G: SizedGrid<'d, MaterialID>>(
+    grid: &G,
+    context: &MeshingContext,
+    loaded: &LoadedMaterials,
+    axis: WorldAxis,
+    depth: u32,
+) -> Vec<FaceInfo> {
+    let [u_axis, v_axis] = axis.slice_plane();
+    let mut result = Vec::new();
 
-                            min_y = std::cmp::min(min_y, size.y);
-                        }
-                    }
+    for side in Side::ALL.into_iter().filter(|it| it.axis() == axis) {
+        let (size, mask) = visible_face_mask(grid, context, loaded, side, depth);
 
-                    // bottom face
-                    if consumed[(x, y, 1)] != depth {
-                        let above = ids_after.and_then(|it| it.get_pos_value(pos));
+        for (i, cell) in mask.into_iter().enumerate() {
+            let Some(cell) = cell else {
+                continue;
+            };
+            let mut position = UVec3::ZERO;
+            position[axis] = depth;
+            position[u_axis] = i as u32 % size.x;
+            position[v_axis] = i as u32 / size.x;
+            result.push(FaceInfo {
+                face: MeshFace::new(side, position, UVec2::ONE),
+                side,
+                value: cell.value,
+                occlusion: cell.occlusion,
+            });
+        }
+    }
 
-                        if is_block_face_visible(above, current, material, loaded) {
-                            let size = check_side(ids_after);
+    result
+}
 
-                            back_faces.push(FaceInfo::new_opposite(
-                                side,
-                                UVec3::new(x as u32, y as u32, depth as u32),
-                                blocks.size(),
-                                size,
-                                material,
-                            ));
+/// Merges visible faces of a layer into as few rectangles as possible.
+///
+/// Only faces with the same value and corner occlusion are merged, so merged
+/// quads shade the same as the faces they replace.
+pub fn greedy_mesh_layer<'d, G: SizedGrid<'d, MaterialID>>(
+    grid: &G,
+    context: &MeshingContext,
+    loaded: &LoadedMaterials,
+    axis: WorldAxis,
+    depth: u32,
+) -> Vec<FaceInfo> {
+    let [u_axis, v_axis] = axis.slice_plane();
+    let mut result = Vec::new();
+
+    for side in Side::ALL.into_iter().filter(|it| it.axis() == axis) {
+        let (size, mut mask) = visible_face_mask(grid, context, loaded, side, depth);
+        let at = |u: u32, v: u32| (u + v * size.x) as usize;
+
+        for v in 0..size.y {
+            let mut u = 0;
+            while u < size.x {
+                let Some(cell) = mask[at(u, v)] else {
+                    u += 1;
+                    continue;
+                };
 
-                            min_y = std::cmp::min(min_y, size.y);
+                let mut width = 1;
+                while u + width < size.x && mask[at(u + width, v)] == Some(cell) {
+                    width += 1;
+                }
+                let mut height = 1;
+                'rows: while v + height < size.y {
+                    for du in 0..width {
+                        if mask[at(u + du, v + height)] != Some(cell) {
+                            break 'rows;
                         }
                     }
+                    height += 1;
+                }
 
-                    y += min_y as usize;
+                for dv in 0..height {
+                    for du in 0..width {
+                        mask[at(u + du, v + dv)] = None;
+                    }
                 }
+
+                let mut position = UVec3::ZERO;
+                position[axis] = depth;
+                position[u_axis] = u;
+                position[v_axis] = v;
+                result.push(FaceInfo {
+                    face: MeshFace::new(side, position, UVec2::new(width, height)),
+                    side,
+                    value: cell.value,
+                    occlusion: cell.occlusion,
+                });
+
+                u += width;
             }
+        }
+    }
 
-            [front_faces, back_faces]
-        })
-        .fold(
-            || [Vec::new(), Vec::new()],
-            |mut acc, it| {
-                let [mut a, mut b] = it;
-                acc[0].append(&mut a);
-                acc[1].append(&mut b);
-                acc
-            },
-        )
+    result
 }
 
-pub fn greedy_mesh<'a, G: SizedGrid<MaterialID>>(
-    blocks: &G,
-    loaded: &'a LoadedMaterials,
-) -> [Vec<FaceInfo<'a>>; Side::COUNT] {
-    [Side::East, Side::Top, Side::South]
-        .into_par_iter()
-        .flat_map(|side| {
-            let blocks = SideView::new(blocks, side);
-
-            let mut consumed =
-                Array::from_elem((blocks.size().x as usize, blocks.size().y as usize, 2), -1);
-
-            (0..blocks.size().z as i32)
-                .into_par_iter()
-                .map(|depth| {
-                    let mut ids_before = SliceView::try_new(&blocks, depth - 1);
-                    let mut ids_at = SliceView::new(&blocks, depth as u32);
-                    let mut ids_after = SliceView::try_new(&blocks, depth + 1);
-
-                    let mut front_faces = Vec::new();
-                    let mut back_faces = Vec::new();
-
-                    for x in 0..blocks.size().x as usize {
-                        let mut y = 0;
-                        while y < blocks.size().y as usize {
-                            let pos = UVec2::new(x as u32, y as u32);
-                            let current = match ids_at.get_pos_value(pos) {
-                                Some(it) => it,
-                                None => {
-                                    consumed[(x, y, 0)] = depth;
-                                    consumed[(x, y, 1)] = depth;
-                                    continue;
-                                }
-                            };
-                            let material = {
-                                #[cfg(debug_assertions)]
-                                {
-                                    loaded.properties.get(current).expect(
-                                        format!("material registry missing id: {}", current)
-                                            .as_str(),
-                                    )
-                                }
-                                #[cfg(not(debug_assertions))]
-                                {
-                                    loaded.properties.get(current).unwrap_unchecked()
-                                }
-                            };
-
-                            let check_side = |side: Option<SliceView<'_, MaterialID, _>>| {
-                                let mut h = 1;
-                                let mut w = 1;
-
-                                let mut pos = |h, w| UVec2::new(x as u32 + h, y as u32 + w);
-
-                                while ids_at.get_pos_value(pos(h, 0)) == Some(current) {
-                                    let above = side.and_then(|it| it.get_pos_value(pos(h, 0)));
-                                    if !is_block_face_visible(above, current, material, loaded) {
-                                        break;
-                                    }
-                                    h += 1;
-                                }
-                                'outer: loop {
-                                    for ih in 0..h {
-                                        if ids_at.get_pos_value(pos(ih, w)) != Some(current) {
-                                            break 'outer;
-                                        }
-                                        let above =
-                                            side.and_then(|it| it.get_pos_value(pos(ih, w)));
-
-                                        if !is_block_face_visible(above, current, material, loaded)
-                                        {
-                                            break 'outer;
-                                        }
-                                    }
-                                    w += 1;
-                                }
-
-                                return IVec2::new(w as i32, h as i32);
-                            };
-
-                            let mut min_y = 1;
-
-                            // top face
-                            if consumed[(x, y, 0)] != depth {
-                                let above = ids_before.and_then(|it| it.get_pos_value(pos));
-
-                                if is_block_face_visible(above, current, material, loaded) {
-                                    let size = check_side(ids_before);
-
-                                    front_faces.push(FaceInfo::new(
-                                        side,
-                                        UVec3::new(x as u32, y as u32, depth as u32),
-                                        size,
-                                        material,
-                                    ));
-
-                                    min_y = std::cmp::min(min_y, size.y);
-                                }
-                            }
-
-                            // bottom face
-                            if consumed[(x, y, 1)] != depth {
-                                let above = ids_after.and_then(|it| it.get_pos_value(pos));
-
-                                if is_block_face_visible(above, current, material, loaded) {
-                                    let size = check_side(ids_after);
-
-                                    back_faces.push(FaceInfo::new_opposite(
-                                        side,
-                                        UVec3::new(x as u32, y as u32, depth as u32),
-                                        blocks.size(),
-                                        size,
-                                        material,
-                                    ));
-
-                                    min_y = std::cmp::min(min_y, size.y);
-                                }
-                            }
-
-                            y += min_y as usize;
-                        }
-                    }
-
-                    [front_faces, back_faces]
-                })
-                .fold(
-                    || [Vec::new(), Vec::new()],
-                    |mut acc, it| {
-                        let [mut a, mut b] = it;
-                        acc[0].append(&mut a);
-                        acc[1].append(&mut b);
-                        acc
-                    },
-                )
-        })
-        .collect()
+/// Meshes all layers of `grid` with the greedy mesher.
+#[inline]
+pub fn greedy_mesh<'d, G: SizedGrid<'d, MaterialID>>(
+    grid: &G,
+    context: &MeshingContext,
+    loaded: &LoadedMaterials,
+) -> Vec<FaceInfo> {
+    Mesher::Greedy.mesh(grid, context, loaded)
 }
 
+/// Returns whether a face of `current` voxel is visible through `above` voxel.
+///
+/// Voxels without registered material properties are treated as opaque.
 #[inline(always)]
 fn is_block_face_visible(
     above: Option<&MaterialID>,
+    above_mat: Option<&MaterialProperties>,
     current: &MaterialID,
-    current_mat: &MaterialProperties,
-    loaded: &LoadedMaterials,
+    current_mat: Option<&MaterialProperties>,
 ) -> bool {
-    let above_mat = above.and_then(|it| loaded.properties.get(it));
-
-    if let Some(above_mat) = above_mat {
-        if above_mat.color.w == 1.0 {
+    if let Some(above) = above {
+        if above == current {
             return false;
         }
-        if above == Some(current) {
-            return false;
+        match above_mat {
+            Some(it) if it.color().w < 1.0 => {}
+            _ => return false,
         }
     }
 
-    current_mat.color.w != 0.0
+    current_mat.map(|it| it.color().w != 0.0).unwrap_or(true)
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct StagedVertex {
     position: UVec3,
     normal: Vec3,
     uv: Vec2,
     material_side: (u16, Side),
+    occlusion: u8,
+}
+impl StagedVertex {
+    /// Bit patterns of float fields; vertices are only merged when they're
+    /// bitwise equal, which keeps [`Eq`] and [`Hash`] consistent.
+    #[inline]
+    fn float_bits(&self) -> [u32; 5] {
+        [
+            self.normal.x.to_bits(),
+            self.normal.y.to_bits(),
+            self.normal.z.to_bits(),
+            self.uv.x.to_bits(),
+            self.uv.y.to_bits(),
+        ]
+    }
+}
+impl PartialEq for StagedVertex {
+    fn eq(&self, other: &Self) -> bool {
+        self.position == other.position
+            && self.material_side == other.material_side
+            && self.occlusion == other.occlusion
+            && self.float_bits() == other.float_bits()
+    }
 }
 impl Eq for StagedVertex {}
 impl Hash for StagedVertex {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        // We don't care about correctness of handling floats for quickly
-        // checking whether vertices match.
-        unsafe {
-            let s: *const StagedVertex = self;
-            state.write(std::slice::from_raw_parts(
-                s as *const u8,
-                std::mem::size_of::<StagedVertex>(),
-            ))
-        }
+        self.position.hash(state);
+        self.float_bits().hash(state);
+        self.material_side.hash(state);
+        self.occlusion.hash(state);
     }
 }
 
-const MISSING_VOXEL_FACE: FaceProperties = FaceProperties {
+pub(crate) const MISSING_VOXEL_FACE: FaceProperties = FaceProperties {
     base_color: Vec4::new(0.86, 0.08, 0.24, 1.),
     roughness: 0.8,
     metallic: 0.2,
     reflectance: 0.9,
     uv: Vec2::new(0., 0.),
+    uv_size: Vec2::new(0., 0.),
     emissive_color: Vec4::new(0., 0., 0., 1.),
 };
 
+/// Light reaching a vertex for each ambient occlusion level.
+const OCCLUSION_CURVE: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
+
 #[derive(Debug, Clone)]
 pub struct MeshBuilder {
     vertices: IndexSet<StagedVertex, ahash::RandomState>,
@@ -624,57 +776,60 @@ impl MeshBuilder {
         self.indices.push(i as u32);
     }
 
-    pub fn push_face(&mut self, id: u16, side: Side, corners: [UVec3; 4]) {
-        self.push(StagedVertex {
-            position: corners[0],
-            normal: side.normal(),
-            uv: Vec2::new(0.0, 0.0),
-            material_side: (id, side),
-        });
-        self.push(StagedVertex {
-            position: corners[1],
-            normal: side.normal(),
-            uv: Vec2::new(1.0, 0.0),
-            material_side: (id, side),
-        });
-        self.push(StagedVertex {
-            position: corners[2],
-            normal: side.normal(),
-            uv: Vec2::new(0.0, 1.0),
-            material_side: (id, side),
-        });
-        self.push(StagedVertex {
-            position: corners[1],
-            normal: side.normal(),
-            uv: Vec2::new(1.0, 0.0),
-            material_side: (id, side),
-        });
-        self.push(StagedVertex {
-            position: corners[3],
-            normal: side.normal(),
-            uv: Vec2::new(1.0, 1.0),
-            material_side: (id, side),
-        });
-        self.push(StagedVertex {
-            position: corners[2],
-            normal: side.normal(),
-            uv: Vec2::new(0.0, 1.0),
-            material_side: (id, side),
-        });
+    pub fn push_face_info(&mut self, face: &FaceInfo) {
+        self.push_face(face.value, face.side, face.face.corners, face.occlusion);
+    }
+
+    /// Pushes a quad with `corners` in [`MeshFace`] order.
+    ///
+    /// UVs are measured in voxels so that textures repeat across merged
+    /// faces. Quad is split along the diagonal whose corners are less
+    /// occluded so that occlusion is interpolated the same way regardless of
+    /// face orientation.
+    pub fn push_face(&mut self, id: u16, side: Side, corners: [UVec3; 4], occlusion: [u8; 4]) {
+        let width = (corners[1].as_vec3() - corners[0].as_vec3()).length();
+        let height = (corners[2].as_vec3() - corners[0].as_vec3()).length();
+        let uvs = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(width, 0.0),
+            Vec2::new(0.0, height),
+            Vec2::new(width, height),
+        ];
+
+        let order = if occlusion[0] + occlusion[3] > occlusion[1] + occlusion[2] {
+            [0, 1, 3, 0, 3, 2]
+        } else {
+            [0, 1, 2, 1, 3, 2]
+        };
+        for i in order {
+            self.push(StagedVertex {
+                position: corners[i],
+                normal: side.direction(),
+                uv: uvs[i],
+                material_side: (id, side),
+                occlusion: occlusion[i],
+            });
+        }
     }
 
+    /// Builds the mesh and returns it together with the material face each
+    /// [face index](ChunkMaterial::ATTRIBUTE_FACE_INDEX) refers to.
+    ///
+    /// Face index `0` is reserved for voxels with missing material properties.
+    /// Returned faces are meant to be passed to [`ChunkMaterial::new`].
     pub fn build(
         self,
         chunk: &ChunkStore<MaterialID>,
         materials: &LoadedMaterials,
-    ) -> (Mesh, Vec<FaceProperties>) {
-        let mut face_properties: IndexSet<&FaceProperties> =
+    ) -> (Mesh, Vec<Option<(MaterialID, Side)>>) {
+        let mut face_sources: IndexSet<Option<(&MaterialID, Side)>> =
             IndexSet::with_capacity(self.vertices.len() / 8);
-        face_properties.insert(&MISSING_VOXEL_FACE);
+        face_sources.insert(None);
 
         let mut positions = Vec::with_capacity(self.vertices.len());
         let mut normals = Vec::with_capacity(self.vertices.len());
         let mut uvs = Vec::with_capacity(self.vertices.len());
+        let mut occlusions = Vec::with_capacity(self.vertices.len());
         let mut face_indices: Vec<u32> = Vec::with_capacity(self.vertices.len() / 8);
 
         for StagedVertex {
@@ -682,45 +837,127 @@ impl MeshBuilder {
             normal,
             uv,
             material_side,
+            occlusion,
         } in &self.vertices
         {
             positions.push(position.as_vec3().to_array());
             normals.push(normal.to_array());
             uvs.push(uv.to_array());
+            occlusions.push(OCCLUSION_CURVE[*occlusion as usize]);
 
             let id = chunk
                 .value_of_index(material_side.0)
                 .expect("invalid chunk storage value index");
 
-            if let Some(properties) = materials.properties.get(id) {
-                let key = match properties.faces.as_ref().unwrap() {
-                    crate::data::BlockFaces::Uniform { face } => face_properties.insert_full(face),
-                    crate::data::BlockFaces::Sided {
-                        face,
-                        face_override,
-                    } => {
-                        if let Some(f) = face_override.get(&material_side.1) {
-                            face_properties.insert_full(f)
-                        } else {
-                            face_properties.insert_full(face)
-                        }
-                    }
-                };
-                face_indices.push(key.0 as u32);
+            let key = if materials.contains(id) {
+                face_sources.insert_full(Some((id, material_side.1))).0
             } else {
-                panic!("unable to get material properties for id ({})", id);
-            }
+                // rendered with MISSING_VOXEL_FACE
+                0
+            };
+            face_indices.push(key as u32);
         }
-        face_properties.shrink_to_fit();
-        tracing::info!("Generated face properties: {:#?}", face_properties);
 
         let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
         mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
         mesh.insert_attribute(ChunkMaterial::ATTRIBUTE_FACE_INDEX, face_indices);
+        mesh.insert_attribute(ChunkMaterial::ATTRIBUTE_OCCLUSION, occlusions);
         mesh.insert_indices(Indices::U32(self.indices));
 
-        (mesh, face_properties.into_iter().cloned().collect())
+        (
+            mesh,
+            face_sources
+                .into_iter()
+                .map(|it| it.map(|(id, side)| (*id, side)))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ahash::{HashMap, HashMapExt};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    /// Returns a store of `size` with roughly `density` of voxels filled with
+    /// one of a few materials.
+    fn random_store(seed: u64, size: UVec3, density: f64) -> ChunkStore<MaterialID> {
+        let materials = ["a", "b", "c"].map(|it| MaterialID::new(format!("test:mesh_{}", it)));
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut result = ChunkStore::new(size);
+        for y in 0..size.y {
+            for z in 0..size.z {
+                for x in 0..size.x {
+                    if rng.gen_bool(density) {
+                        let material = materials[rng.gen_range(0..materials.len())];
+                        result.set_pos_value(UVec3::new(x, y, z), Some(material));
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn stores() -> impl Iterator<Item = ChunkStore<MaterialID>> {
+        (0..16).map(|seed| {
+            let density = [0.1, 0.5, 0.9][seed as usize % 3];
+            random_store(seed, UVec3::new(6, 5, 7), density)
+        })
+    }
+
+    fn quad_area(face: &MeshFace) -> u32 {
+        let [origin, u, v, _] = face.corners;
+        (u.as_ivec3() - origin.as_ivec3()).abs().max_element() as u32
+            * (v.as_ivec3() - origin.as_ivec3()).abs().max_element() as u32
+    }
+
+    #[test]
+    fn greedy_area_matches_culled_face_count() {
+        let loaded = LoadedMaterials::default();
+        for store in stores() {
+            let culled = Mesher::Culled.mesh(&store, &MeshingContext::empty(), &loaded);
+            let greedy = Mesher::Greedy.mesh(&store, &MeshingContext::empty(), &loaded);
+
+            assert!(culled.iter().all(|it| quad_area(&it.face) == 1));
+            let area: u32 = greedy.iter().map(|it| quad_area(&it.face)).sum();
+            assert_eq!(area as usize, culled.len());
+            assert!(greedy.len() <= culled.len());
+        }
+    }
+
+    #[test]
+    fn greedy_mesh_is_closed() {
+        let loaded = LoadedMaterials::default();
+        for store in stores() {
+            let faces = Mesher::Greedy.mesh(&store, &MeshingContext::empty(), &loaded);
+
+            // quad edges are split into unit edges, keyed by lower end and
+            // axis, so T-junctions between merged quads still match up
+            let mut edges: HashMap<(UVec3, usize), u32> = HashMap::new();
+            for info in &faces {
+                let [origin, u, v, uv] = info.face.corners;
+                for (a, b) in [(origin, u), (origin, v), (u, uv), (v, uv)] {
+                    let start = a.min(b);
+                    let delta = a.max(b) - start;
+                    let axis = (0..3).find(|it| delta[*it] != 0).unwrap();
+                    for i in 0..delta[axis] {
+                        let mut at = start;
+                        at[axis] += i;
+                        *edges.entry((at, axis)).or_default() += 1;
+                    }
+                }
+            }
+
+            // an edge is shared by two faces, or by four where solid voxels
+            // touch only along the edge
+            for (edge, count) in edges {
+                assert!(count % 2 == 0, "edge {:?} used by {} faces", edge, count);
+            }
+        }
     }
 }
@@ -2,27 +2,43 @@ use std::ptr::addr_of;
 
 use bevy::prelude::*;
 
+pub use content::ChunkContent;
 pub use view::*;
 
+use crate::math::aabb::AABB;
+use crate::math::pos::ChunkPos;
+use crate::math::ray::{Ray, RayHit};
 use crate::math::side::Side;
 use crate::math::vec::IsVec;
 use crate::util::MybOwned;
+use crate::world::gen::WriteMode;
 
 pub mod chunk_material;
+pub mod content;
+pub mod format;
 pub mod mesh;
+pub mod octree;
 pub mod view;
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, clap::ValueEnum)]
 #[repr(u8)]
 #[derive(Default)]
 pub enum Mesher {
+    /// Merges adjacent faces of the same value into larger quads.
     #[default]
     Greedy,
+    /// Emits a quad for every face not hidden by another voxel in the same
+    /// chunk; faces on chunk borders are always emitted.
+    Naive,
+    /// Emits a quad for every visible face, culling border faces against
+    /// neighbouring chunks.
+    Culled,
 }
 
 #[derive(Debug, Default, Component)]
 pub struct ChunkInfo {
     pub mesher: Mesher,
+    pub position: ChunkPos,
 }
 
 pub type ChunkValueIndex = u16;
@@ -31,16 +47,21 @@ pub const CHUNK_FRONT: Side = Side::South;
 
 /// Container for a 3D grid/array of values.
 ///
-/// Chunk values are stored in x, z, y order in a contiguous block of memory.
-/// Default (front-facing) [`Side`] of a chunk is [`south`](Side::South).
+/// Chunk values are stored in x, z, y order. Depending on content, value
+/// indices are stored as a single value, a sparse map or a contiguous block of
+/// memory (see [`ChunkContent`]). Default (front-facing) [`Side`] of a chunk
+/// is [`south`](Side::South).
 #[derive(Debug, Default, Component)]
 pub struct ChunkStore<T: PartialEq> {
     /// Out of order list of values stored in this store
+    ///
+    /// Values stay in the list after the last voxel referring to them is
+    /// changed, until the store is [compacted](ChunkStore::compact).
     pub values: Vec<T>,
     /// Dimensions of the store used to convert [`UVec3`] coordinates into [`content`] index.
     pub size: UVec3,
     /// Ordered sequence of value indices.
-    pub content: Vec<ChunkValueIndex>,
+    pub content: ChunkContent,
 }
 
 impl<T: PartialEq + Clone> Clone for ChunkStore<T> {
@@ -56,9 +77,9 @@ impl<T: PartialEq + Clone> Clone for ChunkStore<T> {
 impl<T: PartialEq> ChunkStore<T> {
     pub fn new(size: UVec3) -> ChunkStore<T> {
         ChunkStore {
-            values: Vec::with_capacity(((size.x * size.y * size.z) as f32 * 0.01).ceil() as usize),
+            values: Vec::new(),
             size,
-            content: vec![0; size.x as usize * size.y as usize * size.z as usize],
+            content: ChunkContent::Uniform(0),
         }
     }
 
@@ -66,12 +87,56 @@ impl<T: PartialEq> ChunkStore<T> {
         ChunkStore {
             values: vec![],
             size: UVec3::ZERO,
-            content: vec![],
+            content: ChunkContent::Uniform(0),
         }
     }
 
+    /// Returns number of voxels in this store.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size.x as usize * self.size.y as usize * self.size.z as usize
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.size == UVec3::ZERO || self.values.len() == 0 && self.content.len() == 0
+        self.len() == 0
+    }
+
+    /// Sets all voxels to value with `index`.
+    pub fn fill(&mut self, index: ChunkValueIndex) {
+        self.content.fill(index);
+    }
+
+    /// Drops values no voxel refers to, rewrites `content` to match and
+    /// switches it to the smallest representation.
+    ///
+    /// Remaining values keep their order. Returns the number of removed
+    /// values.
+    pub fn compact(&mut self) -> usize {
+        let len = self.len();
+        let mut used = vec![false; self.values.len() + 1];
+        self.content.mark_used(len, &mut used);
+        if used[1..].iter().all(|it| *it) {
+            self.content.optimize(len);
+            return 0;
+        }
+
+        // remap[old] is the new index of value old, 0 stays empty
+        let mut remap: Vec<ChunkValueIndex> = vec![0; used.len()];
+        let mut next = 0;
+        for (old, _) in used.iter().enumerate().skip(1).filter(|(_, it)| **it) {
+            next += 1;
+            remap[old] = next;
+        }
+
+        let before = self.values.len();
+        let mut old = 0;
+        self.values.retain(|_| {
+            old += 1;
+            used[old]
+        });
+        self.content.remap(&remap);
+        self.content.optimize(len);
+        before - self.values.len()
     }
 
     pub fn map<'a, V: PartialEq>(&'a self, f: fn(&'a T) -> V) -> ChunkStore<V> {
@@ -91,6 +156,16 @@ impl<T: PartialEq> ChunkStore<T> {
     }
 }
 
+/// Returns positions within `region` that are inside of a grid of `size`, in
+/// x, z, y order.
+fn region_positions(region: AABB<UVec3>, size: UVec3) -> impl Iterator<Item = UVec3> {
+    let start = region.start;
+    let end = region.end.saturating_add(UVec3::ONE).min(size);
+    (start.y..end.y).flat_map(move |y| {
+        (start.z..end.z).flat_map(move |z| (start.x..end.x).map(move |x| UVec3::new(x, y, z)))
+    })
+}
+
 pub trait SizedGrid<'d, T>
 where
     T: 'd,
@@ -132,11 +207,50 @@ where
     }
     #[must_use]
     fn get_pos_value(&self, pos: UVec3) -> Option<&'d T> {
-        if let Some(i) = self.get_pos_key(pos) {
-            self.values().get(i as usize - 1).map(|it| *it)
-        } else {
-            None
+        self.value_of_index(self.get_pos_key(pos)?)
+    }
+    /// Returns the first non-empty voxel `ray` hits within `max_distance`.
+    ///
+    /// `ray` is in grid coordinates, each voxel being a unit cube with its
+    /// lower corner at the voxel position.
+    #[must_use]
+    fn raycast(&self, ray: &Ray, max_distance: f32) -> Option<RayHit> {
+        let size = self.size().as_ivec3();
+        ray.voxels(max_distance).find(|hit| {
+            hit.pos.cmpge(IVec3::ZERO).all()
+                && hit.pos.cmplt(size).all()
+                && self.get_pos_key(hit.pos.as_uvec3()).is_some_and(|it| it != 0)
+        })
+    }
+    /// Copies voxels within `region` into a new store, which only contains
+    /// values used by the copied voxels.
+    ///
+    /// Parts of `region` outside of the grid are cut off.
+    #[must_use]
+    fn copy_region(&self, region: AABB<UVec3>) -> ChunkStore<T>
+    where
+        T: PartialEq + Clone,
+    {
+        let end = region.end.saturating_add(UVec3::ONE).min(self.size());
+        let mut result = ChunkStore::new(end.saturating_sub(region.start));
+
+        let values = self.values();
+        // remap[i] is index of value i in result
+        let mut remap: Vec<Option<ChunkValueIndex>> = vec![None; values.len() + 1];
+        remap[0] = Some(0);
+        for pos in region_positions(region, self.size()) {
+            let Some(key) = self.get_pos_key(pos) else {
+                continue;
+            };
+            let index = *remap[key as usize].get_or_insert_with(|| {
+                result.values.push(values[key as usize - 1].clone());
+                result.values.len() as ChunkValueIndex
+            });
+            result.set_pos_id(pos - region.start, index);
         }
+        let len = result.len();
+        result.content.optimize(len);
+        result
     }
 }
 
@@ -147,13 +261,47 @@ where
     fn get_pos_key_mut(&mut self, pos: UVec3) -> Option<&mut ChunkValueIndex>;
     #[must_use]
     fn value_list_mut(&mut self) -> &mut Vec<T>;
-    fn insert_key(&mut self, key: T) -> u16;
+    /// Appends `key` to the list of values and returns its index, or `None`
+    /// if the list is full.
+    ///
+    /// Never changes indices of values that are already in the list.
+    fn insert_key(&mut self, key: T) -> Option<ChunkValueIndex>;
+    /// Drops values no voxel refers to, making room for new ones. Returns the
+    /// number of removed values.
+    ///
+    /// Indices of remaining values may change, so indices obtained before
+    /// this call must not be used afterwards.
+    fn compact_values(&mut self) -> usize {
+        0
+    }
     #[inline]
     fn set_pos_id(&mut self, pos: UVec3, value: ChunkValueIndex) {
         if let Some(pos) = self.get_pos_key_mut(pos) {
             *pos = value;
         }
     }
+    /// Returns index of `value`, adding it to the list of values if needed.
+    ///
+    /// If the list of values is full, it's [compacted](Self::compact_values)
+    /// first, which may change indices obtained earlier. Returns `None` if
+    /// every value is still in use.
+    fn index_or_insert(&mut self, value: T) -> Option<ChunkValueIndex>
+    where
+        T: PartialEq,
+    {
+        if let Some(index) = self.index_of_value(&value) {
+            return Some(index);
+        }
+        if self.value_list_mut().len() >= MAX_CHUNK_VALUES {
+            self.compact_values();
+        }
+        self.insert_key(value)
+    }
+    /// Sets voxel at `pos` to `value`, adding it to the list of values if
+    /// needed.
+    ///
+    /// The write is skipped if the value can't be
+    /// [inserted](Self::index_or_insert).
     fn set_pos_value(&mut self, pos: UVec3, value: Option<T>)
     where
         T: PartialEq,
@@ -161,32 +309,121 @@ where
         if pos.x >= self.size().x || pos.y >= self.size().y || pos.z >= self.size().z {
             return;
         }
-        let i = value
-            .map(|it| {
-                self.index_of_value(&it).unwrap_or_else(|| {
-                    self.value_list_mut().push(it);
-                    self.values().len() as u16
-                })
-            })
-            .unwrap_or(0);
+        let i = match value.map(|it| self.index_or_insert(it)) {
+            Some(Some(i)) => i,
+            Some(None) => return,
+            None => 0,
+        };
 
         self.set_pos_id(pos, i);
     }
+    /// Like [`set_pos_value`](Self::set_pos_value), but only clones `value`
+    /// if it has to be added to the list of values.
     fn set_or_clone_pos_value(&mut self, pos: UVec3, value: Option<&T>)
     where
         T: PartialEq + Clone,
     {
-        let i = value
-            .map(|it| {
-                self.index_of_value(it).unwrap_or_else(|| {
-                    self.value_list_mut().push(it.clone());
-                    self.values().len() as u16
-                })
-            })
-            .unwrap_or(0);
+        let i = match value {
+            Some(it) => match self
+                .index_of_value(it)
+                .or_else(|| self.index_or_insert(it.clone()))
+            {
+                Some(i) => i,
+                None => return,
+            },
+            None => 0,
+        };
 
         self.set_pos_id(pos, i);
     }
+    /// Sets all voxels within `region` to `value`, adding it to the list of
+    /// values if needed.
+    ///
+    /// Nothing is written if the value can't be
+    /// [inserted](Self::index_or_insert).
+    fn fill_region(&mut self, region: AABB<UVec3>, value: Option<T>)
+    where
+        T: PartialEq,
+    {
+        let index = match value.map(|it| self.index_or_insert(it)) {
+            Some(Some(index)) => index,
+            Some(None) => return,
+            None => 0,
+        };
+        for pos in region_positions(region, self.size()) {
+            self.set_pos_id(pos, index);
+        }
+    }
+    /// Changes all voxels with value index `from` to `to`.
+    fn replace(&mut self, from: ChunkValueIndex, to: ChunkValueIndex) {
+        if self.size().cmpeq(UVec3::ZERO).any() {
+            return;
+        }
+        let all = AABB::new_unchecked(UVec3::ZERO, self.size().saturating_sub(UVec3::ONE));
+        for pos in region_positions(all, self.size()) {
+            if self.get_pos_key(pos) == Some(from) {
+                self.set_pos_id(pos, to);
+            }
+        }
+    }
+    /// Writes voxels of `store` into this grid, with its origin placed at
+    /// `offset`.
+    ///
+    /// Voxels that don't fit into this grid are skipped. Values of `store`
+    /// are added to this grid as needed; if the list of values is full, it's
+    /// [compacted](Self::compact_values) first, and voxels whose value still
+    /// doesn't fit are skipped.
+    fn paste(&mut self, store: &ChunkStore<T>, offset: UVec3, mode: WriteMode)
+    where
+        T: PartialEq + Clone,
+    {
+        if store.size.cmpeq(UVec3::ZERO).any() {
+            return;
+        }
+        if self.value_list_mut().len() >= MAX_CHUNK_VALUES {
+            // before any index is resolved, so none of them is invalidated
+            self.compact_values();
+        }
+        let region = AABB::new_unchecked(UVec3::ZERO, store.size.saturating_sub(UVec3::ONE));
+        // remap[i] is index of store value i in this grid
+        let mut remap: Vec<Option<ChunkValueIndex>> = vec![None; store.values.len() + 1];
+        remap[0] = Some(0);
+        for pos in region_positions(region, store.size) {
+            let target = offset.saturating_add(pos);
+            if !target.cmplt(self.size()).all() {
+                continue;
+            }
+            let (Some(key), Some(existing)) = (store.get_pos_key(pos), self.get_pos_key(target))
+            else {
+                continue;
+            };
+            let write = match mode {
+                WriteMode::Color => key != 0 && existing != 0,
+                WriteMode::Replace => true,
+                WriteMode::Masked => key != 0 && existing == 0,
+            };
+            if !write {
+                continue;
+            }
+
+            let index = match remap[key as usize] {
+                Some(index) => index,
+                None => {
+                    let value = &store.values[key as usize - 1];
+                    let index = match self.index_of_value(value) {
+                        Some(index) => index,
+                        None => match self.insert_key(value.clone()) {
+                            Some(index) => index,
+                            None => continue,
+                        },
+                    };
+                    remap[key as usize] = Some(index);
+                    index
+                }
+            };
+            self.set_pos_id(target, index);
+        }
+    }
 }
 
 impl<'d, T: PartialEq + 'd> SizedGrid<'d, T> for ChunkStore<T> {
@@ -202,7 +439,8 @@ impl<'d, T: PartialEq + 'd> SizedGrid<'d, T> for ChunkStore<T> {
     }
     #[inline]
     fn get_pos_key(&self, pos: UVec3) -> Option<ChunkValueIndex> {
-        self.content.get(self.get_position_index(pos)).cloned()
+        let i = self.get_position_index(pos);
+        (i < self.len()).then(|| self.content.get(i))
     }
     #[inline(always)]
     fn values(&self) -> Vec<&'d T> {
@@ -219,20 +457,243 @@ impl<'d, T: PartialEq + 'd> SizedGrid<'d, T> for ChunkStore<T> {
 }
 
 impl<'d, T: PartialEq + 'd> SizedGridMut<'d, T> for ChunkStore<T> {
+    /// Switches content to dense representation; prefer
+    /// [`set_pos_id`](SizedGridMut::set_pos_id) for writes.
     #[inline]
     fn get_pos_key_mut(&mut self, pos: UVec3) -> Option<&mut ChunkValueIndex> {
         let i = self.get_position_index(pos);
-        self.content.get_mut(i)
+        let len = self.len();
+        self.content.make_dense(len).get_mut(i)
+    }
+    #[inline]
+    fn set_pos_id(&mut self, pos: UVec3, value: ChunkValueIndex) {
+        let i = self.get_position_index(pos);
+        let len = self.len();
+        if i < len {
+            self.content.set(i, value, len);
+        }
+    }
+    fn fill_region(&mut self, region: AABB<UVec3>, value: Option<T>)
+    where
+        T: PartialEq,
+    {
+        let index = match value.map(|it| self.index_or_insert(it)) {
+            Some(Some(index)) => index,
+            Some(None) => return,
+            None => 0,
+        };
+        let covers_all = region.end.saturating_add(UVec3::ONE).cmpge(self.size).all();
+        if region.start == UVec3::ZERO && covers_all {
+            self.fill(index);
+            return;
+        }
+        for pos in region_positions(region, self.size) {
+            self.set_pos_id(pos, index);
+        }
+        let len = self.len();
+        self.content.optimize(len);
+    }
+    fn replace(&mut self, from: ChunkValueIndex, to: ChunkValueIndex) {
+        let len = self.len();
+        if from == 0 || to == 0 {
+            // sparse content doesn't store empty voxels, so they can't be
+            // remapped in place
+            let values: Vec<_> = self.content.iter(len).collect();
+            for (i, value) in values.into_iter().enumerate() {
+                if value == from {
+                    self.content.set(i, to, len);
+                }
+            }
+        } else {
+            let mut remap: Vec<ChunkValueIndex> =
+                (0..=self.values.len() as ChunkValueIndex).collect();
+            if let Some(it) = remap.get_mut(from as usize) {
+                *it = to;
+            }
+            self.content.remap(&remap);
+        }
+        self.content.optimize(len);
     }
     #[inline(always)]
     fn value_list_mut(&mut self) -> &mut Vec<T> {
         &mut self.values
     }
-    fn insert_key(&mut self, key: T) -> u16 {
-        if self.values.len() + 1 > MAX_CHUNK_VALUES {
-            panic!("overfull chunk palette");
+    fn insert_key(&mut self, key: T) -> Option<ChunkValueIndex> {
+        if self.values.len() >= MAX_CHUNK_VALUES {
+            return None;
         }
         self.values.push(key);
-        self.values.len() as u16
+        Some(self.values.len() as ChunkValueIndex)
+    }
+    #[inline]
+    fn compact_values(&mut self) -> usize {
+        self.compact()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns values of all voxels of `store`, in x, z, y order.
+    fn voxel_values<T: PartialEq + Clone>(store: &ChunkStore<T>) -> Vec<Option<T>> {
+        let all = AABB::new_unchecked(UVec3::ZERO, store.size - UVec3::ONE);
+        region_positions(all, store.size)
+            .map(|pos| store.get_pos_value(pos).cloned())
+            .collect()
+    }
+
+    #[test]
+    fn compact_uniform_content() {
+        let mut store = ChunkStore::new(UVec3::splat(4));
+        store.values = vec![10, 20, 30];
+        store.fill(2);
+
+        assert_eq!(store.compact(), 2);
+        assert_eq!(store.values, [20]);
+        assert!(matches!(store.content, ChunkContent::Uniform(1)));
+    }
+
+    #[test]
+    fn compact_sparse_content() {
+        let mut store = ChunkStore::new(UVec3::splat(8));
+        store.values = vec![10, 20, 30];
+        store.set_pos_id(UVec3::new(1, 2, 3), 1);
+        store.set_pos_id(UVec3::new(7, 7, 7), 3);
+        store.set_pos_id(UVec3::new(0, 5, 0), 3);
+        let before = voxel_values(&store);
+
+        assert_eq!(store.compact(), 1);
+        assert_eq!(store.values, [10, 30]);
+        assert!(matches!(store.content, ChunkContent::Sparse(_)));
+        assert_eq!(voxel_values(&store), before);
+    }
+
+    #[test]
+    fn compact_dense_content() {
+        let mut store = ChunkStore::new(UVec3::new(4, 3, 5));
+        store.values = vec![10, 20, 30, 40];
+        let all = AABB::new_unchecked(UVec3::ZERO, store.size - UVec3::ONE);
+        for (i, pos) in region_positions(all, store.size).enumerate() {
+            store.set_pos_id(pos, [2, 4, 0][i % 3]);
+        }
+        let before = voxel_values(&store);
+
+        assert_eq!(store.compact(), 2);
+        assert_eq!(store.values, [20, 40]);
+        assert!(matches!(store.content, ChunkContent::Dense(_)));
+        assert_eq!(voxel_values(&store), before);
+        // nothing left to remove
+        assert_eq!(store.compact(), 0);
+        assert_eq!(voxel_values(&store), before);
+    }
+
+    #[test]
+    fn full_palette_is_only_compacted_on_write() {
+        let mut store = ChunkStore::new(UVec3::splat(2));
+        store.values = (0..MAX_CHUNK_VALUES as u32).collect();
+        store.fill(1);
+
+        // existing indices stay valid
+        assert_eq!(store.insert_key(u32::MAX), None);
+        assert_eq!(store.values.len(), MAX_CHUNK_VALUES);
+
+        store.set_pos_value(UVec3::ONE, Some(u32::MAX));
+        assert_eq!(store.values, [0, u32::MAX]);
+        assert_eq!(store.get_pos_value(UVec3::ZERO), Some(&0));
+        assert_eq!(store.get_pos_value(UVec3::ONE), Some(&u32::MAX));
+    }
+
+    #[test]
+    fn empty_voxels_have_no_value() {
+        let mut store = ChunkStore::new(UVec3::new(2, 1, 1));
+        store.set_pos_value(UVec3::X, Some(7u32));
+        assert_eq!(store.get_pos_value(UVec3::ZERO), None);
+        assert_eq!(store.get_pos_value(UVec3::X), Some(&7));
+        // outside of the grid
+        assert_eq!(store.get_pos_value(UVec3::Y), None);
+    }
+
+    /// Returns a row of voxels with `values`, `None` being empty.
+    fn row(values: &[Option<u32>]) -> ChunkStore<u32> {
+        let mut result = ChunkStore::new(UVec3::new(values.len() as u32, 1, 1));
+        for (x, value) in values.iter().enumerate() {
+            result.set_pos_value(UVec3::new(x as u32, 0, 0), *value);
+        }
+        result
+    }
+
+    fn paste_row(mode: WriteMode) -> Vec<Option<u32>> {
+        let mut target = row(&[Some(10), None, Some(10), None]);
+        let source = row(&[Some(20), Some(20), None, None]);
+        target.paste(&source, UVec3::ZERO, mode);
+        voxel_values(&target)
+    }
+
+    #[test]
+    fn paste_modes() {
+        assert_eq!(paste_row(WriteMode::Color), [Some(20), None, Some(10), None]);
+        assert_eq!(paste_row(WriteMode::Replace), [Some(20), Some(20), None, None]);
+        assert_eq!(paste_row(WriteMode::Masked), [Some(10), Some(20), Some(10), None]);
+    }
+
+    #[test]
+    fn paste_clips_to_grid_and_reuses_values() {
+        let mut target = row(&[Some(10), None, None, None]);
+        let mut source = ChunkStore::new(UVec3::splat(2));
+        source.values = vec![30, 10];
+        source.fill(1);
+        source.set_pos_id(UVec3::new(1, 0, 0), 2);
+
+        target.paste(&source, UVec3::new(2, 0, 0), WriteMode::Replace);
+        assert_eq!(voxel_values(&target), [Some(10), None, Some(30), Some(10)]);
+        assert_eq!(target.values, [10, 30]);
+
+        // empty stores don't write anything
+        target.paste(&ChunkStore::new(UVec3::ZERO), UVec3::ZERO, WriteMode::Replace);
+        assert_eq!(voxel_values(&target), [Some(10), None, Some(30), Some(10)]);
+    }
+
+    #[test]
+    fn copy_region_keeps_only_used_values() {
+        let store = row(&[Some(10), Some(20), None, Some(30), Some(20)]);
+        assert_eq!(store.values, [10, 20, 30]);
+
+        let region = AABB::new_unchecked(UVec3::new(1, 0, 0), UVec3::new(2, 0, 0));
+        let copy = store.copy_region(region);
+        assert_eq!(copy.size, UVec3::new(2, 1, 1));
+        assert_eq!(copy.values, [20]);
+        assert_eq!(voxel_values(&copy), [Some(20), None]);
+
+        // parts outside of the grid are cut off
+        let region = AABB::new_unchecked(UVec3::new(3, 0, 0), UVec3::splat(10));
+        let copy = store.copy_region(region);
+        assert_eq!(copy.size, UVec3::new(2, 1, 1));
+        assert_eq!(copy.values, [30, 20]);
+        assert_eq!(voxel_values(&copy), [Some(30), Some(20)]);
+    }
+
+    #[test]
+    fn fill_region_inserts_value() {
+        let mut store = row(&[Some(10), Some(10), Some(10), Some(10)]);
+        store.fill_region(
+            AABB::new_unchecked(UVec3::new(1, 0, 0), UVec3::new(2, 0, 0)),
+            Some(20),
+        );
+        assert_eq!(voxel_values(&store), [Some(10), Some(20), Some(20), Some(10)]);
+        assert_eq!(store.values, [10, 20]);
+
+        store.fill_region(AABB::new_unchecked(UVec3::ZERO, UVec3::splat(10)), None);
+        assert_eq!(voxel_values(&store), [None; 4]);
+        assert!(matches!(store.content, ChunkContent::Uniform(0)));
+    }
+
+    #[test]
+    fn replace_covers_whole_grid() {
+        let mut store = row(&[Some(10), Some(20), Some(10)]);
+        store.replace(1, 2);
+        assert_eq!(voxel_values(&store), [Some(20); 3]);
+        store.replace(2, 0);
+        assert_eq!(voxel_values(&store), [None; 3]);
     }
 }
@@ -0,0 +1,375 @@
+//! Sparse voxel octree storage.
+//!
+//! [`VoxelOctree`] stores value indices in an octree whose nodes are collapsed
+//! into a single leaf whenever all voxels they cover share a value, so large
+//! empty or uniform regions take up a single node. It's meant for big, mostly
+//! static volumes such as imported models and distant terrain; [`ChunkStore`]
+//! is faster for chunks that are edited often.
+
+use std::ptr::addr_of;
+
+use bevy::prelude::*;
+
+use super::{ChunkContent, ChunkStore, ChunkValueIndex, SizedGrid, SizedGridMut, MAX_CHUNK_VALUES};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OctreeNode {
+    /// All voxels covered by the node have the same value index.
+    Leaf(ChunkValueIndex),
+    /// Child octants, indexed by `x | y << 1 | z << 2` bits of their position
+    /// within the node.
+    Branch(Box<[OctreeNode; 8]>),
+}
+
+impl OctreeNode {
+    #[inline]
+    fn octant(pos: UVec3, level: u32) -> usize {
+        let bit = |it: u32| ((it >> (level - 1)) & 1) as usize;
+        bit(pos.x) | (bit(pos.y) << 1) | (bit(pos.z) << 2)
+    }
+
+    fn get(&self, pos: UVec3, level: u32) -> ChunkValueIndex {
+        match self {
+            OctreeNode::Leaf(value) => *value,
+            OctreeNode::Branch(children) => {
+                children[OctreeNode::octant(pos, level)].get(pos, level - 1)
+            }
+        }
+    }
+
+    /// Splits leaves on the way to voxel at `pos` and returns its leaf.
+    fn leaf_mut(&mut self, pos: UVec3, level: u32) -> &mut ChunkValueIndex {
+        if level == 0 {
+            return match self {
+                OctreeNode::Leaf(value) => value,
+                OctreeNode::Branch(_) => unreachable!("branch at voxel level"),
+            };
+        }
+        if let OctreeNode::Leaf(value) = self {
+            let value = *value;
+            *self = OctreeNode::Branch(Box::new(std::array::from_fn(|_| OctreeNode::Leaf(value))));
+        }
+        match self {
+            OctreeNode::Branch(children) => {
+                children[OctreeNode::octant(pos, level)].leaf_mut(pos, level - 1)
+            }
+            OctreeNode::Leaf(_) => unreachable!(),
+        }
+    }
+
+    fn set(&mut self, pos: UVec3, level: u32, value: ChunkValueIndex) {
+        match self {
+            OctreeNode::Leaf(current) if *current == value => return,
+            OctreeNode::Leaf(_) if level == 0 => {
+                *self = OctreeNode::Leaf(value);
+                return;
+            }
+            OctreeNode::Leaf(current) => {
+                let current = *current;
+                *self = OctreeNode::Branch(Box::new(std::array::from_fn(|_| {
+                    OctreeNode::Leaf(current)
+                })));
+            }
+            OctreeNode::Branch(_) => {}
+        }
+        if let OctreeNode::Branch(children) = self {
+            children[OctreeNode::octant(pos, level)].set(pos, level - 1, value);
+        }
+        self.collapse_children();
+    }
+
+    /// Replaces a branch whose children are all leaves with the same value by
+    /// a single leaf.
+    fn collapse_children(&mut self) {
+        let OctreeNode::Branch(children) = self else {
+            return;
+        };
+        let OctreeNode::Leaf(first) = children[0] else {
+            return;
+        };
+        if children
+            .iter()
+            .all(|it| matches!(it, OctreeNode::Leaf(value) if *value == first))
+        {
+            *self = OctreeNode::Leaf(first);
+        }
+    }
+
+    /// Collapses all uniform subtrees.
+    fn collapse(&mut self) {
+        if let OctreeNode::Branch(children) = self {
+            for child in children.iter_mut() {
+                child.collapse();
+            }
+        }
+        self.collapse_children();
+    }
+
+    /// Builds node covering `2^level` voxels from `origin` in each direction.
+    fn build(origin: UVec3, level: u32, get: &impl Fn(UVec3) -> ChunkValueIndex) -> OctreeNode {
+        if level == 0 {
+            return OctreeNode::Leaf(get(origin));
+        }
+        let half = 1 << (level - 1);
+        let mut result = OctreeNode::Branch(Box::new(std::array::from_fn(|octant| {
+            let offset = UVec3::new(
+                (octant as u32 & 1) * half,
+                ((octant as u32 >> 1) & 1) * half,
+                ((octant as u32 >> 2) & 1) * half,
+            );
+            OctreeNode::build(origin + offset, level - 1, get)
+        })));
+        result.collapse_children();
+        result
+    }
+
+    fn node_count(&self) -> usize {
+        match self {
+            OctreeNode::Leaf(_) => 1,
+            OctreeNode::Branch(children) => {
+                1 + children.iter().map(OctreeNode::node_count).sum::<usize>()
+            }
+        }
+    }
+}
+
+/// Sparse voxel octree with the same value palette semantics as
+/// [`ChunkStore`].
+///
+/// The root node covers the smallest power of two cube containing `size`;
+/// voxels outside of `size` are empty and can't be changed.
+#[derive(Debug, Clone)]
+pub struct VoxelOctree<T: PartialEq> {
+    /// Out of order list of values stored in this octree
+    pub values: Vec<T>,
+    size: UVec3,
+    /// Number of levels below the root; the root covers `2^depth` voxels
+    /// along each axis.
+    depth: u32,
+    root: OctreeNode,
+}
+
+impl<T: PartialEq> VoxelOctree<T> {
+    /// Creates an empty octree of given `size`.
+    pub fn new(size: UVec3) -> VoxelOctree<T> {
+        VoxelOctree {
+            values: Vec::new(),
+            size,
+            depth: size.max_element().next_power_of_two().trailing_zeros(),
+            root: OctreeNode::Leaf(0),
+        }
+    }
+
+    #[inline]
+    fn contains(&self, pos: UVec3) -> bool {
+        pos.cmplt(self.size).all()
+    }
+
+    /// Returns number of nodes in the tree, which is proportional to its
+    /// memory use.
+    pub fn node_count(&self) -> usize {
+        self.root.node_count()
+    }
+
+    /// Collapses uniform subtrees left behind by writes through
+    /// [`get_pos_key_mut`](SizedGridMut::get_pos_key_mut).
+    pub fn collapse(&mut self) {
+        self.root.collapse();
+    }
+}
+
+impl<T: PartialEq> From<ChunkStore<T>> for VoxelOctree<T> {
+    fn from(store: ChunkStore<T>) -> Self {
+        let mut result = VoxelOctree::new(store.size);
+        result.root = OctreeNode::build(UVec3::ZERO, result.depth, &|pos| {
+            if pos.cmplt(store.size).all() {
+                store.content.get(store.get_position_index(pos))
+            } else {
+                0
+            }
+        });
+        result.values = store.values;
+        result
+    }
+}
+
+impl<T: PartialEq> From<VoxelOctree<T>> for ChunkStore<T> {
+    fn from(octree: VoxelOctree<T>) -> Self {
+        let mut result = ChunkStore::new(octree.size);
+        let mut content = Vec::with_capacity(result.len());
+        for y in 0..octree.size.y {
+            for z in 0..octree.size.z {
+                for x in 0..octree.size.x {
+                    content.push(octree.root.get(UVec3::new(x, y, z), octree.depth));
+                }
+            }
+        }
+        result.content = ChunkContent::from_dense(content);
+        result.values = octree.values;
+        result
+    }
+}
+
+impl<'d, T: PartialEq + 'd> SizedGrid<'d, T> for VoxelOctree<T> {
+    #[inline(always)]
+    fn size(&self) -> UVec3 {
+        self.size
+    }
+    /// Returns index the position would have in a [`ChunkStore`] of the same
+    /// size.
+    #[inline]
+    fn get_position_index(&self, pos: UVec3) -> usize {
+        pos.x as usize
+            + pos.z as usize * self.size.x as usize
+            + pos.y as usize * self.size.x as usize * self.size.z as usize
+    }
+    #[inline]
+    fn get_pos_key(&self, pos: UVec3) -> Option<ChunkValueIndex> {
+        self.contains(pos).then(|| self.root.get(pos, self.depth))
+    }
+    #[inline(always)]
+    fn values(&self) -> Vec<&'d T> {
+        let mut result = Vec::with_capacity(self.values.len());
+        for v in &self.values {
+            unsafe {
+                // SAFETY: Data is guaranteed to be valid for 'd
+                let d = addr_of!(*v);
+                result.push(d.as_ref().unwrap_unchecked())
+            }
+        }
+        result
+    }
+}
+
+impl<'d, T: PartialEq + 'd> SizedGridMut<'d, T> for VoxelOctree<T> {
+    /// Splits nodes down to the voxel at `pos`; call
+    /// [`collapse`](VoxelOctree::collapse) after writing through the
+    /// reference, or use [`set_pos_id`](SizedGridMut::set_pos_id) instead.
+    fn get_pos_key_mut(&mut self, pos: UVec3) -> Option<&mut ChunkValueIndex> {
+        if !self.contains(pos) {
+            return None;
+        }
+        Some(self.root.leaf_mut(pos, self.depth))
+    }
+    #[inline(always)]
+    fn value_list_mut(&mut self) -> &mut Vec<T> {
+        &mut self.values
+    }
+    fn insert_key(&mut self, key: T) -> Option<ChunkValueIndex> {
+        if self.values.len() >= MAX_CHUNK_VALUES {
+            return None;
+        }
+        self.values.push(key);
+        Some(self.values.len() as ChunkValueIndex)
+    }
+    fn set_pos_id(&mut self, pos: UVec3, value: ChunkValueIndex) {
+        if self.contains(pos) {
+            self.root.set(pos, self.depth, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a store of `size` with a few values spread in a pattern that
+    /// doesn't line up with octree nodes.
+    fn patterned_store(size: UVec3) -> ChunkStore<u32> {
+        let mut result = ChunkStore::new(size);
+        result.values = vec![10, 20, 30];
+        for y in 0..size.y {
+            for z in 0..size.z {
+                for x in 0..size.x {
+                    let index = (x * 7 + y * 3 + z * 5) % 4;
+                    result.set_pos_id(UVec3::new(x, y, z), index as ChunkValueIndex);
+                }
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn uniform_writes_collapse() {
+        let size = UVec3::splat(4);
+        let mut octree = VoxelOctree::<u32>::new(size);
+        octree.values = vec![10];
+        assert_eq!(octree.node_count(), 1);
+
+        octree.set_pos_id(UVec3::new(1, 2, 3), 1);
+        assert!(octree.node_count() > 1);
+        octree.set_pos_id(UVec3::new(1, 2, 3), 0);
+        assert_eq!(octree.node_count(), 1);
+
+        for y in 0..size.y {
+            for z in 0..size.z {
+                for x in 0..size.x {
+                    octree.set_pos_id(UVec3::new(x, y, z), 1);
+                }
+            }
+        }
+        assert_eq!(octree.node_count(), 1);
+        assert_eq!(octree.get_pos_key(UVec3::new(3, 0, 2)), Some(1));
+    }
+
+    #[test]
+    fn writes_through_reference_collapse_on_request() {
+        let mut octree = VoxelOctree::<u32>::new(UVec3::splat(4));
+        octree.values = vec![10];
+        *octree.get_pos_key_mut(UVec3::ONE).unwrap() = 0;
+        assert!(octree.node_count() > 1);
+        octree.collapse();
+        assert_eq!(octree.node_count(), 1);
+    }
+
+    #[test]
+    fn chunk_store_round_trip() {
+        for size in [
+            UVec3::ONE,
+            UVec3::new(3, 5, 7),
+            UVec3::new(6, 2, 9),
+            UVec3::splat(8),
+        ] {
+            let store = patterned_store(size);
+            let octree = VoxelOctree::from(store.clone());
+            assert_eq!(octree.size(), size);
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    for x in 0..size.x {
+                        let pos = UVec3::new(x, y, z);
+                        assert_eq!(octree.get_pos_key(pos), store.get_pos_key(pos));
+                    }
+                }
+            }
+
+            let result = ChunkStore::from(octree);
+            assert_eq!(result.size, store.size);
+            assert_eq!(result.values, store.values);
+            assert!(result
+                .content
+                .iter(result.len())
+                .eq(store.content.iter(store.len())));
+        }
+    }
+
+    #[test]
+    fn out_of_bounds_access() {
+        // root covers 4×4×4 voxels, only part of it is inside
+        let size = UVec3::new(3, 2, 3);
+        let mut octree = VoxelOctree::from(patterned_store(size));
+        let nodes = octree.node_count();
+
+        for pos in [
+            UVec3::new(3, 0, 0),
+            UVec3::new(0, 2, 0),
+            UVec3::new(0, 0, 3),
+            UVec3::splat(100),
+        ] {
+            assert_eq!(octree.get_pos_key(pos), None);
+            assert!(octree.get_pos_key_mut(pos).is_none());
+            octree.set_pos_id(pos, 1);
+            assert_eq!(octree.get_pos_key(pos), None);
+        }
+        assert_eq!(octree.node_count(), nodes);
+    }
+}
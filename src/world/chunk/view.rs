@@ -154,11 +154,9 @@ impl<'d, T: PartialEq, G: SizedGrid<'d, T>> SliceView<'d, T, G> {
 
     #[must_use]
     pub fn get_pos_value(&self, pos: UVec2) -> Option<&'d T> {
-        if let Some(i) = self.get_pos_key(pos) {
-            self.values().get(i as usize - 1).map(|it| *it)
-        } else {
-            None
-        }
+        // index 0 is empty
+        let i = (self.get_pos_key(pos)? as usize).checked_sub(1)?;
+        self.values().get(i).copied()
     }
 }
 
@@ -230,7 +228,7 @@ impl<'d, T: PartialEq + 'd, G: SizedGridMut<'d, T>> SliceViewMut<'d, T, G> {
         self.inner.value_list_mut()
     }
     #[inline(always)]
-    pub fn insert_key(&mut self, key: T) -> u16 {
+    pub fn insert_key(&mut self, key: T) -> Option<ChunkValueIndex> {
         self.inner.insert_key(key)
     }
     #[must_use]
@@ -253,18 +251,14 @@ impl<'d, T: PartialEq + 'd, G: SizedGridMut<'d, T>> SliceViewMut<'d, T, G> {
 
     #[inline]
     pub fn set_pos_id(&mut self, pos: UVec2, value: ChunkValueIndex) {
-        if let Some(pos) = self.get_pos_key_mut(pos) {
-            *pos = value;
-        }
+        self.inner.set_pos_id(pos.extend(self.depth), value);
     }
 
     #[must_use]
     pub fn get_pos_value(&self, pos: UVec2) -> Option<&'d T> {
-        if let Some(i) = self.get_pos_key(pos) {
-            self.get_values().get(i as usize - 1).map(|it| *it)
-        } else {
-            None
-        }
+        // index 0 is empty
+        let i = (self.get_pos_key(pos)? as usize).checked_sub(1)?;
+        self.get_values().get(i).copied()
     }
 
     pub fn set_pos_value(&mut self, pos: UVec2, value: Option<T>)
@@ -274,14 +268,14 @@ impl<'d, T: PartialEq + 'd, G: SizedGridMut<'d, T>> SliceViewMut<'d, T, G> {
         if pos.x >= self.size().x || pos.y >= self.size().y {
             return;
         }
-        let i = value
-            .map(|it| {
-                self.index_of_value(&it).unwrap_or_else(|| {
-                    self.value_list_mut().push(it);
-                    self.get_values().len() as u16
-                })
-            })
-            .unwrap_or(0);
+        let i = match value {
+            Some(it) => match self.index_of_value(&it).or_else(|| self.insert_key(it)) {
+                Some(i) => i,
+                // list of values is full
+                None => return,
+            },
+            None => 0,
+        };
 
         self.set_pos_id(pos, i);
     }
@@ -290,14 +284,14 @@ impl<'d, T: PartialEq + 'd, G: SizedGridMut<'d, T>> SliceViewMut<'d, T, G> {
     where
         T: PartialEq + Clone,
     {
-        let i = value
-            .map(|it| {
-                self.index_of_value(it).unwrap_or_else(|| {
-                    self.value_list_mut().push(it.clone());
-                    self.get_values().len() as u16
-                })
-            })
-            .unwrap_or(0);
+        let i = match value {
+            Some(it) => match self.index_of_value(it).or_else(|| self.insert_key(it.clone())) {
+                Some(i) => i,
+                // list of values is full
+                None => return,
+            },
+            None => 0,
+        };
 
         self.set_pos_id(pos, i);
     }
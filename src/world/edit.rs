@@ -0,0 +1,242 @@
+//! Reading and writing blocks of loaded chunks in global block coordinates.
+//!
+//! [`VoxelWorld`] routes block positions to the chunk entity owning them, so
+//! gameplay code doesn't have to deal with chunk-local positions. Writes
+//! schedule affected chunks for re-meshing and are reported through
+//! [`BlockChanged`] events.
+//!
+//! Changes are collected over the frame and sent in a single batch by
+//! [`flush_block_changes`], so systems reacting to them (lighting, physics,
+//! sound, networking) can run after [`BlockChangeSet`] and see every change
+//! made during the frame exactly once.
+
+use ahash::{HashMap, HashMapExt};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::math::pos::ChunkPos;
+use crate::math::ray::{Ray, RayHit};
+use crate::world::chunk::mesh::MeshingState;
+use crate::world::chunk::{ChunkStore, SizedGrid, SizedGridMut};
+use crate::world::material::MaterialID;
+use crate::world::stream::LoadedChunks;
+use crate::world::WorldInfo;
+
+/// What caused a block to change.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BlockChangeCause {
+    #[default]
+    Unknown,
+    /// Block was placed or broken by a player entity.
+    Player(Entity),
+    /// Block was changed by world simulation, e.g. physics or fluids.
+    Simulation,
+}
+
+/// Sent whenever a block is changed through [`VoxelWorld`].
+///
+/// Empty blocks are reported as [`MaterialID::AIR`]. A block changed several
+/// times within a frame is reported once, with `old` material from before the
+/// first change and `new` material and `cause` of the last one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Event)]
+pub struct BlockChanged {
+    /// Global block position.
+    pub pos: IVec3,
+    pub old: MaterialID,
+    pub new: MaterialID,
+    pub cause: BlockChangeCause,
+}
+
+/// Block changes made during the current frame, not sent yet.
+#[derive(Debug, Default, Resource)]
+pub struct PendingBlockChanges {
+    changes: Vec<BlockChanged>,
+    /// Index of the pending change of each block position.
+    index: HashMap<IVec3, usize>,
+}
+
+impl PendingBlockChanges {
+    fn push(&mut self, change: BlockChanged) {
+        match self.index.get(&change.pos) {
+            Some(&i) => {
+                let pending = &mut self.changes[i];
+                pending.new = change.new;
+                pending.cause = change.cause;
+            }
+            None => {
+                self.index.insert(change.pos, self.changes.len());
+                self.changes.push(change);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Access to blocks of loaded chunks in global block coordinates.
+#[derive(SystemParam)]
+pub struct VoxelWorld<'w, 's> {
+    world: Query<'w, 's, &'static WorldInfo>,
+    loaded: Res<'w, LoadedChunks>,
+    chunks: Query<
+        'w,
+        's,
+        (
+            &'static mut ChunkStore<MaterialID>,
+            &'static mut MeshingState,
+        ),
+    >,
+    changes: ResMut<'w, PendingBlockChanges>,
+}
+
+impl<'w, 's> VoxelWorld<'w, 's> {
+    /// Returns chunk size of the world, or `None` if no world is open.
+    pub fn chunk_size(&self) -> Option<UVec3> {
+        self.world.get_single().ok().map(|it| it.chunk_size)
+    }
+
+    /// Returns entity of the chunk containing block at `pos` along with the
+    /// position of the block within the chunk.
+    fn locate(&self, pos: IVec3) -> Option<(Entity, UVec3)> {
+        let (chunk, local) = ChunkPos::from_block(pos, self.chunk_size()?);
+        Some((*self.loaded.chunks.get(&chunk)?, local))
+    }
+
+    /// Returns whether the chunk containing block at `pos` is loaded.
+    pub fn is_loaded(&self, pos: IVec3) -> bool {
+        self.locate(pos)
+            .is_some_and(|(entity, _)| self.chunks.contains(entity))
+    }
+
+    /// Returns material of block at `pos`, or `None` if its chunk isn't
+    /// loaded.
+    pub fn get_block(&self, pos: IVec3) -> Option<MaterialID> {
+        let (entity, local) = self.locate(pos)?;
+        let (store, _) = self.chunks.get(entity).ok()?;
+        Some(block_at(store, local))
+    }
+
+    /// Returns the first non-empty block `ray` hits within `max_distance`.
+    ///
+    /// `ray` is in world coordinates, blocks in chunks that aren't loaded
+    /// are treated as empty.
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Option<RayHit> {
+        ray.voxels(max_distance).find(|hit| {
+            self.get_block(hit.pos)
+                .is_some_and(|it| it != MaterialID::AIR)
+        })
+    }
+
+    /// Changes material of block at `pos`; [`MaterialID::AIR`] clears it.
+    ///
+    /// Returns `false` if the chunk containing the block isn't loaded or every
+    /// value of its palette is in use, in which case nothing is changed.
+    pub fn set_block(
+        &mut self,
+        pos: IVec3,
+        material: MaterialID,
+        cause: BlockChangeCause,
+    ) -> bool {
+        let Some((entity, local)) = self.locate(pos) else {
+            return false;
+        };
+        let Ok((mut store, mut state)) = self.chunks.get_mut(entity) else {
+            // chunk is still loading
+            return false;
+        };
+
+        let old = block_at(&store, local);
+        if old == material {
+            return true;
+        }
+        let index = if material == MaterialID::AIR {
+            Some(0)
+        } else if let Some(index) = store.index_of_value(&material) {
+            Some(index)
+        } else {
+            match store.insert_key(material) {
+                Some(index) => Some(index),
+                None => {
+                    // compaction changes indices the mesh cache refers to
+                    if store.compact_values() > 0 {
+                        state.invalidate_all();
+                    }
+                    store.insert_key(material)
+                }
+            }
+        };
+        let Some(index) = index else {
+            tracing::warn!("Palette of chunk containing {} is full", pos);
+            return false;
+        };
+        store.set_pos_id(local, index);
+        state.mark_dirty(local);
+
+        // faces of neighbouring chunks next to the block (culled against it or
+        // occluded by it) have to be updated, including ones across edges
+        // and corners
+        let size = store.size;
+        let chunk = ChunkPos::from_block(pos, size).0;
+        for offset in MeshingState::touched_neighbours(local, size) {
+            let Some(&neighbour) = self.loaded.chunks.get(&(chunk + offset)) else {
+                continue;
+            };
+            if let Ok((_, mut state)) = self.chunks.get_mut(neighbour) {
+                // the voxel of the neighbour nearest to the block; layers
+                // re-meshed around it cover every face touching the block
+                let nearest = (local.as_ivec3() - offset * size.as_ivec3())
+                    .clamp(IVec3::ZERO, size.as_ivec3() - IVec3::ONE);
+                state.mark_dirty(nearest.as_uvec3());
+            }
+        }
+
+        self.changes.push(BlockChanged {
+            pos,
+            old,
+            new: material,
+            cause,
+        });
+        true
+    }
+}
+
+/// Systems that send [`BlockChanged`] events; readers should run after it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemSet)]
+pub struct BlockChangeSet;
+
+/// Sends block changes made during the frame as [`BlockChanged`] events.
+pub fn flush_block_changes(
+    mut pending: ResMut<PendingBlockChanges>,
+    mut events: EventWriter<BlockChanged>,
+) {
+    if pending.is_empty() {
+        return;
+    }
+    pending.index.clear();
+    // blocks changed back within the frame didn't change at all
+    events.send_batch(pending.changes.drain(..).filter(|it| it.old != it.new));
+}
+
+fn block_at(store: &ChunkStore<MaterialID>, local: UVec3) -> MaterialID {
+    store
+        .get_pos_key(local)
+        .and_then(|it| store.value_of_index(it))
+        .copied()
+        .unwrap_or(MaterialID::AIR)
+}
+
+pub struct VoxelWorldPlugin;
+
+impl Plugin for VoxelWorldPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BlockChanged>()
+            .init_resource::<PendingBlockChanges>()
+            .add_systems(PostUpdate, flush_block_changes.in_set(BlockChangeSet));
+    }
+}
@@ -11,6 +11,9 @@ pub trait TerrainGenerator<T: PartialEq> {
     fn generate(&self, pos: Vec3, blocks: &mut ChunkStore<T>);
 }
 
+/// Decides which voxels are written when combining grids, see
+/// [`SizedGridMut::paste`](crate::world::chunk::SizedGridMut::paste).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WriteMode {
     /// Write only over existing content
     Color,
@@ -26,13 +29,10 @@ pub struct Fill {
 
 impl TerrainGenerator<MaterialID> for Fill {
     fn generate(&self, _pos: Vec3, blocks: &mut ChunkStore<MaterialID>) {
-        let id = blocks.insert_key(self.material.clone());
-        for y in 0..(blocks.size.y) {
-            for x in 0..(blocks.size.x) {
-                for z in 0..(blocks.size.z) {
-                    blocks.set_pos_id(UVec3::new(x, y, z), id)
-                }
-            }
-        }
+        let Some(id) = blocks.insert_key(self.material) else {
+            tracing::error!("Chunk palette is full, skipping fill");
+            return;
+        };
+        blocks.fill(id);
     }
 }
@@ -11,21 +11,26 @@ pub struct SimplexChunkGen {
     pub dirt_height: u8,
 }
 
-const FILL_STONE: Fill = Fill {
-    material: MaterialID::Static("common:stone"),
-};
-
 impl TerrainGenerator<MaterialID> for SimplexChunkGen {
     fn generate(&self, pos: Vec3, blocks: &mut crate::world::chunk::ChunkStore<MaterialID>) {
         let fbm: Fbm<Simplex> = Fbm::new(self.seed);
 
         if pos.y < 0. {
-            FILL_STONE.generate(pos, blocks);
+            Fill {
+                material: MaterialID::new("common:stone"),
+            }
+            .generate(pos, blocks);
+            return;
         }
 
-        let stone = blocks.insert_key(MaterialID::Static("common:stone"));
-        let dirt = blocks.insert_key(MaterialID::Static("common:dirt"));
-        let grass = blocks.insert_key(MaterialID::Static("common:grass"));
+        let (Some(stone), Some(dirt), Some(grass)) = (
+            blocks.insert_key(MaterialID::new("common:stone")),
+            blocks.insert_key(MaterialID::new("common:dirt")),
+            blocks.insert_key(MaterialID::new("common:grass")),
+        ) else {
+            tracing::error!("Chunk palette is full, skipping terrain generation");
+            return;
+        };
 
         for z in 0..blocks.size.z {
             for x in 0..blocks.size.x {
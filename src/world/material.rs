@@ -5,9 +5,13 @@ use bevy::prelude::*;
 
 use crate::decl_id_type;
 
-decl_id_type!(MaterialID);
+decl_id_type!(MaterialID {
+    AIR: 0 = "air",
+    // placeholder for blocks whose material can't be determined
+    MISSING: 1 = "missing",
+});
 
-const AIR_ID: MaterialID = MaterialID::Static("air");
+const AIR_ID: MaterialID = MaterialID::AIR;
 
 impl MaterialID {
     #[must_use]
@@ -31,7 +35,7 @@ impl Block {
     pub fn material_id(&self) -> Option<MaterialID> {
         Some(match self {
             Block::None => AIR_ID,
-            Block::MaterialID(material) => material.clone(),
+            Block::MaterialID(material) => *material,
             _ => return None,
         })
     }
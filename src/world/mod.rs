@@ -1,15 +1,27 @@
 use bevy::prelude::*;
 use rand::RngCore;
+use serde::{Deserialize, Serialize};
 
+use crate::data::LoadedMaterials;
 use crate::entity::player::PlayerChunk;
+use crate::math::pos::ChunkPos;
+use crate::world::chunk::chunk_material::ChunkMaterial;
+use crate::world::chunk::mesh::{MeshBuilder, MeshCache, MeshingContext, MeshingState};
+use crate::world::chunk::{ChunkInfo, ChunkStore, Mesher};
+use crate::world::gen::TerrainGenerator;
+use crate::world::material::MaterialID;
+use crate::world::stream::LoadedChunks;
 
 pub mod chunk;
+pub mod edit;
 pub mod gen;
 pub mod material;
 pub mod meta;
+pub mod save;
+pub mod stream;
 //pub mod vox;
 
-#[derive(Debug, Component)]
+#[derive(Debug, Clone, Component, Serialize, Deserialize)]
 pub struct WorldInfo {
     pub seed: u32,
     pub chunk_size: UVec3,
@@ -32,6 +44,15 @@ pub struct World {
     spatial: SpatialBundle,
 }
 
+impl World {
+    pub fn new(info: WorldInfo) -> Self {
+        World {
+            info,
+            ..default()
+        }
+    }
+}
+
 impl Default for World {
     fn default() -> Self {
         World {
@@ -194,97 +215,140 @@ pub fn spawn_chunk_markers(
         ..default()
     });
 }
+*/
 
-pub fn build_fresh_chunks(
+/// Re-meshes chunks whose [`MeshingState`] is dirty.
+///
+/// Border faces are culled and occluded against neighbouring chunks that are
+/// loaded at the time; chunks are re-meshed again as their neighbours load. Chunks are
+/// spawned hidden and shown once they have a mesh.
+#[allow(clippy::type_complexity)]
+pub fn mesh_chunks(
     mut commands: Commands,
-    mut unbuilt: Query<(Entity, &ChunkInfo, &ChunkStore<MaterialID>), Without<ChunkMesh>>,
-
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut chunk_material_assets: ResMut<Assets<ChunkMaterial>>,
-
+    mut chunks: Query<(
+        Entity,
+        &ChunkInfo,
+        &mut MeshingState,
+        &mut MeshCache,
+        Option<&Handle<Mesh>>,
+        Option<&Handle<ChunkMaterial>>,
+        &mut Visibility,
+    )>,
+    stores: Query<&ChunkStore<MaterialID>>,
+    loaded: Res<LoadedChunks>,
     materials: Res<LoadedMaterials>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut chunk_materials: ResMut<Assets<ChunkMaterial>>,
 ) {
-    for (chunk, info, store) in unbuilt.iter_mut() {
-        let meshes = match info.mesher {
-            Mesher::Greedy => greedy_mesh(store, &materials),
+    for (entity, info, mut state, mut cache, mesh, material, mut visibility) in chunks.iter_mut() {
+        if !state.is_dirty() {
+            continue;
+        }
+        let Ok(store) = stores.get(entity) else {
+            continue;
         };
 
-        let (mesh, face_properties) = mesh_builder.build(store, &materials);
+        let context = MeshingContext::from_fn(|offset| {
+            let neighbour = loaded.chunks.get(&(info.position + offset))?;
+            stores.get(*neighbour).ok()
+        });
+        cache.update(&mut state, &context, store.size, |axis, depth| {
+            info.mesher
+                .mesh_layer(store, &context, &materials, axis, depth)
+        });
 
-        commands
-            .entity(chunk)
-            .insert(chunk_material_assets.add(ChunkMaterial {
-                face_properties,
-                ..default()
-            }))
-            .insert(meshes.add(mesh))
-            .insert(ChunkMesh { dirty: false })
-            .insert(Visibility::Visible);
+        if cache.face_count() == 0 {
+            commands
+                .entity(entity)
+                .remove::<(Handle<Mesh>, Handle<ChunkMaterial>)>();
+            continue;
+        }
+
+        let mut builder = MeshBuilder::new();
+        for face in cache.faces() {
+            builder.push_face_info(face);
+        }
+        let (new_mesh, face_sources) = builder.build(store, &materials);
+        let new_material = ChunkMaterial::new(face_sources, &materials);
+
+        // existing assets are replaced in place so handles stay valid
+        match mesh {
+            Some(handle) => meshes.insert(handle, new_mesh),
+            None => {
+                commands.entity(entity).insert(meshes.add(new_mesh));
+            }
+        }
+        match material {
+            Some(handle) => chunk_materials.insert(handle, new_material),
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(chunk_materials.add(new_material));
+            }
+        }
+        if *visibility == Visibility::Hidden {
+            *visibility = Visibility::Inherited;
+        }
     }
 }
-*/
 
 pub fn track_player_chunk(
     mut query: Query<(&Transform, &mut PlayerChunk), Changed<Transform>>,
     world_info: Query<&WorldInfo>,
 ) {
-    let (t, chunk) = query.single();
+    let Ok((t, mut chunk)) = query.get_single_mut() else {
+        return;
+    };
     let pos = t.translation;
 
     if let Ok(world) = world_info.get_single() {
-        let new_x = (pos.x / world.chunk_size.x as f32) as u32;
-        let new_y = (pos.y / world.chunk_size.y as f32) as u32;
-        let new_z = (pos.z / world.chunk_size.z as f32) as u32;
-
-        if chunk.x != new_x || chunk.y != new_y || chunk.z != new_z {
-            let mut chunk = query.single_mut().1;
-            chunk.x = new_x;
-            chunk.y = new_y;
-            chunk.z = new_z;
+        let new_chunk = ChunkPos::from_world(pos, world.chunk_size);
+
+        if chunk.0 != new_chunk {
+            chunk.0 = new_chunk;
         }
     }
 }
 
-/*
-pub fn on_chunk_change(
-    _commands: Commands,
-    _player_chunk: Query<&PlayerChunk, Changed<PlayerChunk>>,
-    _chunks: Query<(Entity, &Transform, &ChunkMesh)>,
-) {
-}
-*/
-
-/*
 #[derive(Debug, Bundle)]
 pub struct Chunk {
     pub info: ChunkInfo,
     pub blocks: ChunkStore<MaterialID>,
+    pub meshing: MeshingState,
+    pub mesh_cache: MeshCache,
     pub spatial: SpatialBundle,
 }
 
 impl Chunk {
-    pub fn new(pos: Vec3, size: UVec3) -> Chunk {
+    pub fn new(position: ChunkPos, size: UVec3) -> Chunk {
+        Chunk::with_blocks(position, ChunkStore::new(size))
+    }
+
+    pub fn with_blocks(position: ChunkPos, blocks: ChunkStore<MaterialID>) -> Chunk {
+        let translation = position.world_origin(blocks.size);
         Chunk {
             info: ChunkInfo {
-                mesher: Mesher::Greedy,
+                mesher: Mesher::default(),
+                position,
             },
-            blocks: ChunkStore::new(size),
+            blocks,
+            meshing: MeshingState::default(),
+            mesh_cache: MeshCache::default(),
             spatial: SpatialBundle {
                 visibility: Visibility::Hidden,
-                transform: Transform::from_translation(pos),
+                transform: Transform::from_translation(translation),
                 ..default()
             },
         }
     }
 
     pub fn new_gen<G: TerrainGenerator<MaterialID>>(
-        pos: Vec3,
+        position: ChunkPos,
         size: UVec3,
         generator: &G,
     ) -> Chunk {
-        let mut result = Chunk::new(pos, size);
-        generator.generate(pos, &mut result.blocks);
+        let mut result = Chunk::new(position, size);
+        generator.generate(position.value.as_vec3(), &mut result.blocks);
         result
     }
 }
-*/
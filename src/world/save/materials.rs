@@ -0,0 +1,121 @@
+//! Material IDs stored in chunk palettes of a world save.
+//!
+//! Runtime [`MaterialID`]s are assigned in order of first use, so they change
+//! whenever content packs are added or removed. Saved chunks refer to
+//! materials by indices into a per-world [`MaterialTable`] instead, which is
+//! stored as a list of material names and only ever grows.
+
+use std::path::Path;
+
+use ahash::{HashMap, HashMapExt};
+
+use crate::data::{LoadedContentPacks, LoadedMaterials};
+use crate::error::StorageError;
+use crate::world::material::MaterialID;
+
+/// Mapping between material names and IDs used in saved chunk palettes.
+#[derive(Debug, Default, Clone)]
+pub struct MaterialTable {
+    /// Materials indexed by their saved ID.
+    materials: Vec<MaterialID>,
+    saved_ids: HashMap<MaterialID, u32>,
+    /// Whether IDs were added since the table was last written.
+    dirty: bool,
+}
+
+impl MaterialTable {
+    pub fn new(materials: Vec<MaterialID>) -> MaterialTable {
+        let mut saved_ids = HashMap::with_capacity(materials.len());
+        for (i, id) in materials.iter().enumerate() {
+            saved_ids.entry(*id).or_insert(i as u32);
+        }
+        MaterialTable {
+            materials,
+            saved_ids,
+            dirty: false,
+        }
+    }
+
+    /// Reads table stored in `path`, returning an empty table if the file
+    /// doesn't exist.
+    pub fn load(path: &Path) -> Result<MaterialTable, StorageError> {
+        if !path.exists() {
+            return Ok(MaterialTable::default());
+        }
+        let names = std::fs::read_to_string(path)?;
+        Ok(MaterialTable::new(ron::from_str(&names)?))
+    }
+
+    /// Writes the table into `path` if it changed since it was loaded or last
+    /// saved.
+    pub fn save(&mut self, path: &Path) -> Result<(), StorageError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let names = ron::ser::to_string_pretty(&self.materials, ron::ser::PrettyConfig::default())?;
+
+        // same as world info, a crash mustn't leave a truncated table behind
+        let temp_path = path.with_extension("ron.tmp");
+        std::fs::write(&temp_path, names)?;
+        std::fs::rename(temp_path, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Returns saved ID of material `id`, assigning a new one if the material
+    /// wasn't saved before.
+    pub fn saved_id(&mut self, id: MaterialID) -> u32 {
+        if let Some(saved) = self.saved_ids.get(&id) {
+            return *saved;
+        }
+        let saved = self.materials.len() as u32;
+        self.materials.push(id);
+        self.saved_ids.insert(id, saved);
+        self.dirty = true;
+        saved
+    }
+
+    /// Returns material with `saved` ID.
+    pub fn material(&self, saved: u32) -> Option<MaterialID> {
+        self.materials.get(saved as usize).copied()
+    }
+
+    #[inline]
+    pub fn materials(&self) -> &[MaterialID] {
+        &self.materials
+    }
+
+    /// Logs saved materials that aren't loaded, grouped by their content
+    /// pack.
+    ///
+    /// Blocks of such materials keep their IDs so that they come back once
+    /// the pack is installed again; until then they're rendered with the
+    /// missing material face.
+    pub fn report_missing(&self, packs: &LoadedContentPacks, loaded: &LoadedMaterials) {
+        let mut missing_packs: HashMap<&str, usize> = HashMap::new();
+        for id in &self.materials {
+            if loaded.contains(id) {
+                continue;
+            }
+            // built-in IDs like air don't belong to any pack
+            let Some((pack, _)) = id.name().split_once(':') else {
+                continue;
+            };
+            if packs.0.iter().any(|it| it.id == pack) {
+                tracing::warn!("World uses material '{}' which no longer exists", id);
+            } else {
+                *missing_packs.entry(pack).or_default() += 1;
+            }
+        }
+
+        let mut missing_packs: Vec<_> = missing_packs.into_iter().collect();
+        missing_packs.sort_unstable();
+        for (pack, count) in missing_packs {
+            tracing::warn!(
+                "World uses {} material(s) from missing content pack '{}'",
+                count,
+                pack
+            );
+        }
+    }
+}
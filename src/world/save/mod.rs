@@ -0,0 +1,202 @@
+//! World persistence.
+//!
+//! A world save is a directory containing a `world.ron` header with
+//! [`WorldInfo`], a `materials.ron` [material table](materials) and a
+//! `regions` directory with [region files](region).
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use ahash::{HashMap, HashMapExt};
+use bevy::prelude::*;
+
+use crate::arguments::Context;
+use crate::data::{LoadedContentPacks, LoadedMaterials};
+use crate::error::StorageError;
+use crate::math::pos::ChunkPos;
+use crate::world::chunk::ChunkStore;
+use crate::world::material::MaterialID;
+use crate::world::{World, WorldInfo};
+
+pub mod materials;
+pub mod region;
+
+use materials::MaterialTable;
+use region::{region_file_name, region_of, RegionFile};
+
+static SAVES_DIR: &str = "saves";
+static WORLD_INFO_FILE: &str = "world.ron";
+static MATERIALS_FILE: &str = "materials.ron";
+static REGIONS_DIR: &str = "regions";
+
+/// Returns directory containing world saves.
+///
+/// The directory isn't created; [`WorldSave::open`] creates it along with
+/// the opened world.
+pub fn saves_dir() -> PathBuf {
+    #[cfg(not(feature = "dev"))]
+    {
+        dirs::data_dir()
+            .map(|data| data.join(crate::NAME).join(SAVES_DIR))
+            .unwrap_or(PathBuf::new().join(SAVES_DIR))
+    }
+    #[cfg(feature = "dev")]
+    {
+        PathBuf::new().join(SAVES_DIR)
+    }
+}
+
+/// Handle to an opened world save directory.
+///
+/// Cloning the handle is cheap; all clones share opened region files and the
+/// material table.
+#[derive(Debug, Clone, Resource)]
+pub struct WorldSave {
+    root: PathBuf,
+    regions: Arc<Mutex<HashMap<IVec3, RegionFile>>>,
+    // always locked after regions
+    materials: Arc<Mutex<MaterialTable>>,
+}
+
+impl WorldSave {
+    pub fn open(root: impl AsRef<Path>) -> Result<WorldSave, StorageError> {
+        let root = root.as_ref().to_path_buf();
+        std::fs::create_dir_all(root.join(REGIONS_DIR))?;
+        let materials = MaterialTable::load(&root.join(MATERIALS_FILE))?;
+
+        Ok(WorldSave {
+            root,
+            regions: Arc::new(Mutex::new(HashMap::new())),
+            materials: Arc::new(Mutex::new(materials)),
+        })
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Reads `world.ron` header, returning `None` if the world is new.
+    pub fn load_info(&self) -> Result<Option<WorldInfo>, StorageError> {
+        let path = self.root.join(WORLD_INFO_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let info_str = std::fs::read_to_string(path)?;
+        Ok(Some(ron::from_str(&info_str)?))
+    }
+
+    pub fn save_info(&self, info: &WorldInfo) -> Result<(), StorageError> {
+        let info_str = ron::ser::to_string_pretty(info, ron::ser::PrettyConfig::default())?;
+
+        // Write into a temporary file first so a crash can't leave a
+        // truncated header behind.
+        let temp_path = self.root.join(WORLD_INFO_FILE).with_extension("ron.tmp");
+        std::fs::write(&temp_path, info_str)?;
+        std::fs::rename(temp_path, self.root.join(WORLD_INFO_FILE))?;
+        Ok(())
+    }
+
+    fn with_region<R>(
+        &self,
+        region: IVec3,
+        f: impl FnOnce(&mut RegionFile) -> Result<R, StorageError>,
+    ) -> Result<R, StorageError> {
+        let mut regions = self.regions.lock().expect("region cache poisoned");
+        let file = match regions.entry(region) {
+            std::collections::hash_map::Entry::Occupied(it) => it.into_mut(),
+            std::collections::hash_map::Entry::Vacant(it) => {
+                let path = self.root.join(REGIONS_DIR).join(region_file_name(region));
+                it.insert(RegionFile::open(path)?)
+            }
+        };
+        f(file)
+    }
+
+    pub fn load_chunk(
+        &self,
+        pos: ChunkPos,
+    ) -> Result<Option<ChunkStore<MaterialID>>, StorageError> {
+        let (region, local) = region_of(pos);
+        let path = self.root.join(REGIONS_DIR).join(region_file_name(region));
+        if !path.exists() {
+            return Ok(None);
+        }
+        self.with_region(region, |file| {
+            let table = self.materials.lock().expect("material table poisoned");
+            file.read_chunk(local, &table)
+        })
+    }
+
+    pub fn save_chunk(
+        &self,
+        pos: ChunkPos,
+        store: &ChunkStore<MaterialID>,
+    ) -> Result<(), StorageError> {
+        let (region, local) = region_of(pos);
+        self.with_region(region, |file| {
+            let mut table = self.materials.lock().expect("material table poisoned");
+            file.write_chunk(local, store, &mut table)?;
+            // saved as soon as IDs are added so chunks rarely outlive them
+            table.save(&self.root.join(MATERIALS_FILE))
+        })
+    }
+
+    pub fn flush(&self) -> Result<(), StorageError> {
+        let mut regions = self.regions.lock().expect("region cache poisoned");
+        for file in regions.values_mut() {
+            file.flush()?;
+        }
+        let mut table = self.materials.lock().expect("material table poisoned");
+        table.save(&self.root.join(MATERIALS_FILE))
+    }
+
+    /// Returns a copy of the world material table.
+    pub fn materials(&self) -> MaterialTable {
+        self.materials
+            .lock()
+            .expect("material table poisoned")
+            .clone()
+    }
+}
+
+/// Opens world selected by [`Context::world`], creating it if it doesn't
+/// exist yet, and spawns its [`World`] entity.
+///
+/// Neither the world nor [`WorldSave`] are inserted if the save can't be
+/// opened; pipe into [`report_open_error`] to log the error.
+pub fn open_world(
+    mut commands: Commands,
+    context: Res<Context>,
+    packs: Res<LoadedContentPacks>,
+    materials: Res<LoadedMaterials>,
+) -> Result<(), StorageError> {
+    let save = WorldSave::open(saves_dir().join(&context.world))?;
+
+    let info = match save.load_info()? {
+        Some(info) => {
+            tracing::info!("Loaded world '{}' (seed: {})", context.world, info.seed);
+            info
+        }
+        None => {
+            let info = WorldInfo::default();
+            tracing::info!("Creating world '{}' (seed: {})", context.world, info.seed);
+            if let Err(err) = save.save_info(&info) {
+                tracing::error!("Unable to save world info: {}", err);
+            }
+            info
+        }
+    };
+
+    save.materials().report_missing(&packs, &materials);
+
+    commands.spawn(World::new(info));
+    commands.insert_resource(save);
+    Ok(())
+}
+
+pub fn report_open_error(In(result): In<Result<(), StorageError>>, context: Res<Context>) {
+    if let Err(err) = result {
+        tracing::error!("Unable to open world '{}': {}", context.world, err);
+    }
+}
@@ -0,0 +1,296 @@
+//! Region files group [`REGION_SIZE`]³ chunks into a single file.
+//!
+//! Each file starts with a header followed by an offset table with one
+//! `(offset, length)` entry per chunk slot. Chunk data is stored zlib
+//! compressed anywhere after the table; a `length` of `0` marks an empty slot.
+//!
+//! Written data is always appended at the end of the file before the table
+//! entry is updated, so an interrupted write leaves the previously stored
+//! chunk intact. Old space is left unused.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::error::StorageError;
+use crate::math::pos::ChunkPos;
+use crate::world::chunk::ChunkStore;
+use crate::world::material::MaterialID;
+
+use super::materials::MaterialTable;
+
+/// Number of chunks stored along each axis of a region.
+pub const REGION_SIZE: i32 = 16;
+pub const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const MAGIC: &[u8; 4] = b"VXRG";
+const REGION_VERSION: u8 = 1;
+const TABLE_START: u64 = MAGIC.len() as u64 + 1;
+const TABLE_ENTRY_SIZE: u64 = 8;
+const HEADER_SIZE: u64 = TABLE_START + REGION_CHUNKS as u64 * TABLE_ENTRY_SIZE;
+
+/// Returns region coordinates containing chunk at `chunk` and position of the
+/// chunk within that region.
+#[inline]
+pub fn region_of(chunk: ChunkPos) -> (IVec3, UVec3) {
+    (
+        chunk.value.div_euclid(IVec3::splat(REGION_SIZE)),
+        chunk.value.rem_euclid(IVec3::splat(REGION_SIZE)).as_uvec3(),
+    )
+}
+
+#[inline]
+pub fn region_file_name(region: IVec3) -> String {
+    format!("r.{}.{}.{}.region", region.x, region.y, region.z)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct TableEntry {
+    offset: u32,
+    length: u32,
+}
+
+#[derive(Debug)]
+pub struct RegionFile {
+    path: PathBuf,
+    file: File,
+    table: Box<[TableEntry; REGION_CHUNKS]>,
+}
+
+impl RegionFile {
+    /// Opens region file at `path`, creating an empty one if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<RegionFile, StorageError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let mut table = Box::new([TableEntry::default(); REGION_CHUNKS]);
+
+        if file.metadata()?.len() == 0 {
+            let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+            header.extend_from_slice(MAGIC);
+            header.push(REGION_VERSION);
+            header.resize(HEADER_SIZE as usize, 0);
+            file.write_all(&header)?;
+            file.flush()?;
+        } else {
+            let mut header = vec![0u8; HEADER_SIZE as usize];
+            file.read_exact(&mut header)
+                .map_err(|_| StorageError::InvalidRegion(path.clone()))?;
+            if &header[..MAGIC.len()] != MAGIC {
+                return Err(StorageError::InvalidRegion(path));
+            }
+            if header[MAGIC.len()] != REGION_VERSION {
+                return Err(StorageError::UnsupportedVersion(header[MAGIC.len()]));
+            }
+
+            for (entry, bytes) in table
+                .iter_mut()
+                .zip(header[TABLE_START as usize..].chunks_exact(TABLE_ENTRY_SIZE as usize))
+            {
+                entry.offset = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+                entry.length = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+            }
+        }
+
+        Ok(RegionFile { path, file, table })
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    fn slot_index(local: UVec3) -> usize {
+        let size = REGION_SIZE as usize;
+        local.x as usize + local.z as usize * size + local.y as usize * size * size
+    }
+
+    pub fn contains(&self, local: UVec3) -> bool {
+        self.table[Self::slot_index(local)].length != 0
+    }
+
+    /// Reads chunk stored in `local` slot of this region.
+    pub fn read_chunk(
+        &mut self,
+        local: UVec3,
+        table: &MaterialTable,
+    ) -> Result<Option<ChunkStore<MaterialID>>, StorageError> {
+        let entry = self.table[Self::slot_index(local)];
+        if entry.length == 0 {
+            return Ok(None);
+        }
+
+        let mut compressed = vec![0u8; entry.length as usize];
+        self.file.seek(SeekFrom::Start(entry.offset as u64))?;
+        self.file.read_exact(&mut compressed)?;
+
+        let mut decoder = ZlibDecoder::new(compressed.as_slice());
+        ChunkStore::read_from(&mut decoder, table).map(Some)
+    }
+
+    /// Writes `store` into `local` slot of this region, replacing any
+    /// previously stored chunk.
+    pub fn write_chunk(
+        &mut self,
+        local: UVec3,
+        store: &ChunkStore<MaterialID>,
+        table: &mut MaterialTable,
+    ) -> Result<(), StorageError> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        store.write_to(&mut encoder, table)?;
+        let compressed = encoder.finish()?;
+
+        let offset = self.file.seek(SeekFrom::End(0))?;
+        let entry = TableEntry {
+            offset: u32::try_from(offset)
+                .map_err(|_| StorageError::InvalidRegion(self.path.clone()))?,
+            length: compressed.len() as u32,
+        };
+
+        self.file.write_all(&compressed)?;
+        self.write_entry(Self::slot_index(local), entry)?;
+
+        Ok(())
+    }
+
+    /// Clears `local` slot of this region.
+    pub fn remove_chunk(&mut self, local: UVec3) -> Result<(), StorageError> {
+        self.write_entry(Self::slot_index(local), TableEntry::default())
+    }
+
+    fn write_entry(&mut self, index: usize, entry: TableEntry) -> Result<(), StorageError> {
+        let mut bytes = [0u8; TABLE_ENTRY_SIZE as usize];
+        bytes[0..4].copy_from_slice(&entry.offset.to_le_bytes());
+        bytes[4..8].copy_from_slice(&entry.length.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(
+            TABLE_START + index as u64 * TABLE_ENTRY_SIZE,
+        ))?;
+        self.file.write_all(&bytes)?;
+        self.table[index] = entry;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), StorageError> {
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::{SizedGrid, SizedGridMut};
+
+    /// Returns path of a region file in the temporary directory, removing
+    /// any file left behind by a previous run.
+    fn temp_region(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "voxelbox_{}_{}.region",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Returns a store filled with `materials` materials in rows along x.
+    fn store(size: u32, materials: usize) -> ChunkStore<MaterialID> {
+        let mut result = ChunkStore::new(UVec3::splat(size));
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let i = (x + z * size + y * size * size) as usize % (materials + 1);
+                    let value = (i != 0).then(|| MaterialID::new(format!("test:region_{}", i)));
+                    result.set_pos_value(UVec3::new(x, y, z), value);
+                }
+            }
+        }
+        result
+    }
+
+    fn assert_same(a: &ChunkStore<MaterialID>, b: &ChunkStore<MaterialID>) {
+        assert_eq!(a.size(), b.size());
+        assert_eq!(a.values(), b.values());
+        for y in 0..a.size().y {
+            for z in 0..a.size().z {
+                for x in 0..a.size().x {
+                    let pos = UVec3::new(x, y, z);
+                    assert_eq!(a.get_pos_key(pos), b.get_pos_key(pos));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn write_reopen_read() {
+        let path = temp_region("reopen");
+        let mut table = MaterialTable::default();
+        let stored = store(8, 3);
+        let local = UVec3::new(1, 2, 3);
+        {
+            let mut region = RegionFile::open(&path).unwrap();
+            assert!(region.read_chunk(local, &table).unwrap().is_none());
+            region.write_chunk(local, &stored, &mut table).unwrap();
+            region.flush().unwrap();
+        }
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert!(region.contains(local));
+        assert!(!region.contains(UVec3::ZERO));
+        let read = region.read_chunk(local, &table).unwrap().unwrap();
+        assert_same(&stored, &read);
+
+        region.remove_chunk(local).unwrap();
+        assert!(region.read_chunk(local, &table).unwrap().is_none());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn overwrite_with_larger_and_smaller_data() {
+        let path = temp_region("overwrite");
+        let mut table = MaterialTable::default();
+        let local = UVec3::new(4, 0, 7);
+        let neighbour = UVec3::new(5, 0, 7);
+        let small = store(2, 1);
+        let large = store(16, 12);
+
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write_chunk(local, &small, &mut table).unwrap();
+        region.write_chunk(neighbour, &small, &mut table).unwrap();
+
+        region.write_chunk(local, &large, &mut table).unwrap();
+        assert_same(&large, &region.read_chunk(local, &table).unwrap().unwrap());
+        assert_same(&small, &region.read_chunk(neighbour, &table).unwrap().unwrap());
+
+        region.write_chunk(local, &small, &mut table).unwrap();
+        drop(region);
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_same(&small, &region.read_chunk(local, &table).unwrap().unwrap());
+        assert_same(&small, &region.read_chunk(neighbour, &table).unwrap().unwrap());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn rejects_foreign_files() {
+        let path = temp_region("foreign");
+        std::fs::write(&path, b"not a region").unwrap();
+        assert!(matches!(
+            RegionFile::open(&path),
+            Err(StorageError::InvalidRegion(_))
+        ));
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,358 @@
+//! Loading and unloading of chunks around the player.
+//!
+//! Whenever [`PlayerChunk`] changes, chunks within [`ChunkStreaming::view_radius`]
+//! that aren't loaded yet are queued nearest-first and loaded (or generated)
+//! on the [`AsyncComputeTaskPool`]. Chunks that leave the radius are written
+//! back into the [`WorldSave`] and despawned.
+
+use std::sync::{Arc, Mutex};
+
+use ahash::{HashMap, HashMapExt};
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, IoTaskPool, Task};
+
+use crate::entity::player::PlayerChunk;
+use crate::math::pos::ChunkPos;
+use crate::world::chunk::mesh::MeshingState;
+use crate::world::chunk::{ChunkInfo, ChunkStore, Mesher};
+use crate::world::gen::old::SimplexChunkGen;
+use crate::world::gen::TerrainGenerator;
+use crate::world::material::MaterialID;
+use crate::world::save::WorldSave;
+use crate::world::{mesh_chunks, track_player_chunk, Chunk, WorldInfo};
+
+#[derive(Debug, Resource)]
+pub struct ChunkStreaming {
+    /// Horizontal distance (in chunks) from the player chunk in which chunks
+    /// are kept loaded.
+    pub view_radius: u32,
+    /// Vertical distance (in chunks) from the player chunk in which chunks are
+    /// kept loaded.
+    pub vertical_radius: u32,
+    /// Maximum number of chunk loads started each frame.
+    pub max_loads_per_frame: usize,
+    /// Mesher used for newly loaded chunks.
+    pub mesher: Mesher,
+}
+
+impl Default for ChunkStreaming {
+    fn default() -> Self {
+        ChunkStreaming {
+            view_radius: 8,
+            vertical_radius: 4,
+            max_loads_per_frame: 8,
+            mesher: Mesher::default(),
+        }
+    }
+}
+
+impl ChunkStreaming {
+    /// Returns whether chunk at `offset` from the player chunk is within view
+    /// distance.
+    pub fn in_range(&self, offset: IVec3) -> bool {
+        let horizontal = offset.x * offset.x + offset.z * offset.z;
+        let r = self.view_radius as i32;
+        horizontal <= r * r && offset.y.unsigned_abs() <= self.vertical_radius
+    }
+}
+
+/// Chunk entities that are loaded or being loaded, keyed by chunk position.
+#[derive(Debug, Default, Resource)]
+pub struct LoadedChunks {
+    pub chunks: HashMap<ChunkPos, Entity>,
+}
+
+/// Chunk positions waiting to be loaded, nearest to the player last.
+#[derive(Debug, Default, Resource)]
+pub struct ChunkLoadQueue(Vec<ChunkPos>);
+
+/// Chunks that were unloaded but haven't been written to disk yet.
+///
+/// Loads check this map first so that a chunk unloaded and immediately
+/// reloaded doesn't get read from a stale region file.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct PendingSaves(Arc<Mutex<HashMap<ChunkPos, (u64, ChunkStore<MaterialID>)>>>);
+
+#[derive(Component)]
+pub struct ChunkLoadTask(Task<ChunkStore<MaterialID>>);
+
+fn load_or_generate(
+    save: &WorldSave,
+    pending: &PendingSaves,
+    position: ChunkPos,
+    info: &WorldInfo,
+) -> ChunkStore<MaterialID> {
+    if let Some((_, store)) = pending.0.lock().unwrap().get(&position) {
+        return store.clone();
+    }
+
+    match save.load_chunk(position) {
+        Ok(Some(store)) => return store,
+        Ok(None) => {}
+        Err(err) => {
+            tracing::error!(
+                "Unable to load chunk {}: {}; regenerating it",
+                position,
+                err
+            );
+        }
+    }
+
+    let mut store = ChunkStore::new(info.chunk_size);
+    let gen = SimplexChunkGen {
+        seed: info.seed,
+        dirt_height: 3,
+    };
+    gen.generate(position.value.as_vec3(), &mut store);
+    store.compact();
+    store
+}
+
+fn save_in_background(
+    save: &WorldSave,
+    pending: &PendingSaves,
+    position: ChunkPos,
+    mut store: ChunkStore<MaterialID>,
+) {
+    store.compact();
+    let generation = {
+        let mut pending = pending.0.lock().unwrap();
+        let generation = pending
+            .get(&position)
+            .map(|it| it.0 + 1)
+            .unwrap_or_default();
+        pending.insert(position, (generation, store));
+        generation
+    };
+
+    let save = save.clone();
+    let pending = pending.clone();
+    IoTaskPool::get()
+        .spawn(async move {
+            let store = match pending.0.lock().unwrap().get(&position) {
+                Some((g, store)) if *g == generation => store.clone(),
+                // a newer save of the same chunk has been scheduled
+                _ => return,
+            };
+            if let Err(err) = save.save_chunk(position, &store) {
+                tracing::error!("Unable to save chunk {}: {}", position, err);
+            }
+            let mut pending = pending.0.lock().unwrap();
+            if matches!(pending.get(&position), Some((g, _)) if *g == generation) {
+                pending.remove(&position);
+            }
+        })
+        .detach();
+}
+
+/// Updates the set of loaded chunks when the player enters a new chunk.
+#[allow(clippy::too_many_arguments)]
+pub fn on_chunk_change(
+    mut commands: Commands,
+    player_chunk: Query<&PlayerChunk, Changed<PlayerChunk>>,
+    mut chunks: Query<&mut ChunkStore<MaterialID>>,
+    mut states: Query<&mut MeshingState>,
+    config: Res<ChunkStreaming>,
+    save: Res<WorldSave>,
+    pending: Res<PendingSaves>,
+    mut loaded: ResMut<LoadedChunks>,
+    mut queue: ResMut<ChunkLoadQueue>,
+) {
+    let Ok(player_chunk) = player_chunk.get_single() else {
+        return;
+    };
+    let center = player_chunk.0;
+
+    let mut unloaded = Vec::new();
+    loaded.chunks.retain(|&position, &mut entity| {
+        if config.in_range(position - center) {
+            return true;
+        }
+        unloaded.push(position);
+        // chunks that are still loading don't have a store yet
+        if let Ok(mut store) = chunks.get_mut(entity) {
+            save_in_background(&save, &pending, position, std::mem::take(&mut *store));
+        }
+        commands.entity(entity).despawn_recursive();
+        false
+    });
+    // borders culled against unloaded chunks would leave holes at the edge
+    // of the view distance
+    for position in unloaded {
+        for offset in ChunkPos::neighbour_offsets() {
+            let Some(&neighbour) = loaded.chunks.get(&(position + offset)) else {
+                continue;
+            };
+            if let Ok(mut state) = states.get_mut(neighbour) {
+                state.neighbour_unloaded(-offset);
+            }
+        }
+    }
+
+    let r = config.view_radius as i32;
+    let v = config.vertical_radius as i32;
+    queue.0.clear();
+    for y in -v..=v {
+        for z in -r..=r {
+            for x in -r..=r {
+                let offset = IVec3::new(x, y, z);
+                if config.in_range(offset) && !loaded.chunks.contains_key(&(center + offset)) {
+                    queue.0.push(center + offset);
+                }
+            }
+        }
+    }
+    queue
+        .0
+        .sort_unstable_by_key(|it| std::cmp::Reverse(it.distance_squared(center)));
+}
+
+/// Starts loading queued chunks, nearest ones first.
+pub fn start_chunk_loads(
+    mut commands: Commands,
+    world: Query<(Entity, &WorldInfo)>,
+    config: Res<ChunkStreaming>,
+    save: Res<WorldSave>,
+    pending: Res<PendingSaves>,
+    mut loaded: ResMut<LoadedChunks>,
+    mut queue: ResMut<ChunkLoadQueue>,
+) {
+    let Ok((world, info)) = world.get_single() else {
+        return;
+    };
+    let pool = AsyncComputeTaskPool::get();
+
+    for _ in 0..config.max_loads_per_frame {
+        let Some(position) = queue.0.pop() else {
+            break;
+        };
+        if loaded.chunks.contains_key(&position) {
+            continue;
+        }
+
+        let save = save.clone();
+        let pending = pending.clone();
+        let info = info.clone();
+        let task = pool.spawn(async move { load_or_generate(&save, &pending, position, &info) });
+
+        let entity = commands
+            .spawn((
+                ChunkInfo {
+                    position,
+                    ..default()
+                },
+                ChunkLoadTask(task),
+                SpatialBundle::HIDDEN_IDENTITY,
+            ))
+            .set_parent(world)
+            .id();
+        loaded.chunks.insert(position, entity);
+    }
+}
+
+/// Inserts chunk data of finished load tasks.
+pub fn finish_chunk_loads(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &ChunkInfo, &mut ChunkLoadTask)>,
+    config: Res<ChunkStreaming>,
+) {
+    for (entity, info, mut task) in tasks.iter_mut() {
+        if let Some(store) = block_on(future::poll_once(&mut task.0)) {
+            let mut chunk = Chunk::with_blocks(info.position, store);
+            chunk.info.mesher = config.mesher;
+            commands
+                .entity(entity)
+                .remove::<ChunkLoadTask>()
+                .insert(chunk);
+        }
+    }
+}
+
+/// Lets neighbours of newly loaded chunks (including ones across edges and
+/// corners) re-mesh their borders with the new context so hidden border
+/// faces get culled and ambient occlusion accounts for the new chunk.
+///
+/// Runs before [`mesh_chunks`] so that the neighbours are re-meshed in the
+/// same frame the new chunk is meshed for the first time.
+pub fn notify_chunk_neighbours(
+    added: Query<&ChunkInfo, Added<ChunkStore<MaterialID>>>,
+    mut states: Query<&mut MeshingState>,
+    loaded: Res<LoadedChunks>,
+) {
+    for info in added.iter() {
+        for offset in ChunkPos::neighbour_offsets() {
+            let Some(&neighbour) = loaded.chunks.get(&(info.position + offset)) else {
+                continue;
+            };
+            if let Ok(mut state) = states.get_mut(neighbour) {
+                state.neighbour_loaded(-offset);
+            }
+        }
+    }
+}
+
+/// Writes all loaded chunks into the world save before the app exits.
+pub fn save_loaded_chunks(
+    exit: EventReader<AppExit>,
+    chunks: Query<(&ChunkInfo, &ChunkStore<MaterialID>)>,
+    save: Res<WorldSave>,
+    pending: Res<PendingSaves>,
+) {
+    if exit.is_empty() {
+        return;
+    }
+
+    let mut stores: Vec<(ChunkPos, ChunkStore<MaterialID>)> = pending
+        .0
+        .lock()
+        .unwrap()
+        .drain()
+        .map(|(position, (_, store))| (position, store))
+        .collect();
+    stores.extend(
+        chunks
+            .iter()
+            .map(|(info, store)| (info.position, store.clone())),
+    );
+
+    tracing::info!("Saving {} chunks...", stores.len());
+    for (position, store) in &mut stores {
+        store.compact();
+        if let Err(err) = save.save_chunk(*position, store) {
+            tracing::error!("Unable to save chunk {}: {}", position, err);
+        }
+    }
+    if let Err(err) = save.flush() {
+        tracing::error!("Unable to flush world save: {}", err);
+    }
+}
+
+pub struct ChunkStreamingPlugin;
+
+impl Plugin for ChunkStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkStreaming>()
+            .init_resource::<LoadedChunks>()
+            .init_resource::<ChunkLoadQueue>()
+            .init_resource::<PendingSaves>()
+            .add_systems(
+                Update,
+                (
+                    track_player_chunk,
+                    on_chunk_change,
+                    start_chunk_loads,
+                    finish_chunk_loads,
+                    notify_chunk_neighbours,
+                    mesh_chunks,
+                )
+                    .chain()
+                    .run_if(resource_exists::<WorldSave>),
+            )
+            .add_systems(
+                Last,
+                save_loaded_chunks.run_if(resource_exists::<WorldSave>),
+            );
+    }
+}